@@ -0,0 +1,68 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayrust::vec::Vec3;
+
+const VECTORS: usize = 1024;
+const ROUNDS: usize = 20_000;
+
+// Best of five, so a busy machine shows up as less noise rather than a slower path
+fn time(name: &str, mut f: impl FnMut() -> f64) {
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+    }
+    let per_op = best.as_secs_f64() * 1.0e9 / (VECTORS * ROUNDS) as f64;
+    println!(
+        "{:<16} {:>8.3} ms {:>6.3} ns/op",
+        name,
+        best.as_secs_f64() * 1.0e3,
+        per_op
+    );
+}
+
+fn main() {
+    let layout = if cfg!(feature = "simd") {
+        "simd"
+    } else {
+        "scalar"
+    };
+    println!("Vec3 layout: {}", layout);
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut random = || Vec3::random(-1.0..1.0, &mut rng);
+    let a: Vec<Vec3> = (0..VECTORS).map(|_| random()).collect();
+    let b: Vec<Vec3> = (0..VECTORS).map(|_| random()).collect();
+
+    time("dot", || {
+        let mut sum = 0.0;
+        for _ in 0..ROUNDS {
+            for (a, b) in black_box(&a).iter().zip(&b) {
+                sum += a.dot(*b);
+            }
+        }
+        sum
+    });
+    time("length_squared", || {
+        let mut sum = 0.0;
+        for _ in 0..ROUNDS {
+            for a in black_box(&a) {
+                sum += a.length_squared();
+            }
+        }
+        sum
+    });
+    time("cross", || {
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..ROUNDS {
+            for (a, b) in black_box(&a).iter().zip(&b) {
+                sum += a.cross(*b);
+            }
+        }
+        sum.x()
+    });
+}
@@ -1 +1 @@
-cargo run --release | convert - image.png && xdg-open image.png
+cargo run --release -- --output image.png && xdg-open image.png
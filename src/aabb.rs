@@ -0,0 +1,113 @@
+use super::ray::Ray;
+use super::vec::Point3;
+
+// Keeps the box of a flat primitive lying in an axis plane from having zero thickness
+pub const BOX_PADDING: f64 = 1.0e-4;
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Aabb {
+        Aabb { minimum, maximum }
+    }
+
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.entry(r, t_min, t_max).is_some()
+    }
+
+    // Slab method: clip the ray's interval against the pair of planes of each axis. What is
+    // left starts where the ray enters the box, or at t_min for a ray starting inside.
+    pub fn entry(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction()[axis];
+            let mut t0 = (self.minimum[axis] - r.origin()[axis]) * inv_d;
+            let mut t1 = (self.maximum[axis] - r.origin()[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some(t_min)
+    }
+}
+
+pub fn surrounding_box(a: Aabb, b: Aabb) -> Aabb {
+    Aabb {
+        minimum: Point3::new(
+            a.minimum.x().min(b.minimum.x()),
+            a.minimum.y().min(b.minimum.y()),
+            a.minimum.z().min(b.minimum.z()),
+        ),
+        maximum: Point3::new(
+            a.maximum.x().max(b.maximum.x()),
+            a.maximum.y().max(b.maximum.y()),
+            a.maximum.z().max(b.maximum.z()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::Vec3;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn slabs_hit_a_ray_through_the_box() {
+        let bbox = unit_box();
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(bbox.hit(&r, 0.001, f64::INFINITY));
+        // Diagonal, and from behind with a negative direction on every axis
+        let r = Ray::new(Point3::new(3.0, 3.0, 3.0), Vec3::new(-1.0, -1.0, -1.0), 0.0);
+        assert!(bbox.hit(&r, 0.001, f64::INFINITY));
+        // Starting inside
+        let r = Ray::new(Point3::new(0.5, 0.0, 0.0), Vec3::new(1.0, 0.2, 0.0), 0.0);
+        assert!(bbox.hit(&r, 0.001, f64::INFINITY));
+    }
+
+    #[test]
+    fn slabs_miss_a_ray_beside_or_short_of_the_box() {
+        let bbox = unit_box();
+        let beside = Ray::new(Point3::new(1.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(!bbox.hit(&beside, 0.001, f64::INFINITY));
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(!bbox.hit(&away, 0.001, f64::INFINITY));
+        // The box starts at t = 4
+        let toward = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(!bbox.hit(&toward, 0.001, 3.9));
+    }
+
+    #[test]
+    fn entry_is_where_the_ray_enters() {
+        let bbox = unit_box();
+        let toward = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert_eq!(bbox.entry(&toward, 0.001, f64::INFINITY), Some(4.0));
+        let inside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert_eq!(bbox.entry(&inside, 0.001, f64::INFINITY), Some(0.001));
+    }
+
+    #[test]
+    fn surrounding_box_covers_both() {
+        let other = Aabb::new(Point3::new(0.0, 2.0, -3.0), Point3::new(4.0, 3.0, 0.0));
+        let both = surrounding_box(unit_box(), other);
+        assert_eq!(both.minimum.x(), -1.0);
+        assert_eq!(both.minimum.y(), -1.0);
+        assert_eq!(both.minimum.z(), -3.0);
+        assert_eq!(both.maximum.x(), 4.0);
+        assert_eq!(both.maximum.y(), 3.0);
+        assert_eq!(both.maximum.z(), 1.0);
+    }
+}
@@ -0,0 +1,234 @@
+use rand::{Rng, RngCore};
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use super::aabb::{surrounding_box, Aabb};
+use super::hit::{Hit, HitRecord};
+use super::ray::Ray;
+use super::stats;
+
+// Bounding volume hierarchy over bounded objects. Leaves keep the objects' indices in the
+// world, so a hit can still tell which object it was.
+pub struct BvhNode {
+    bbox: Aabb,
+    left: BvhChild,
+    right: BvhChild,
+}
+
+enum BvhChild {
+    // Up to the tree's leaf size of objects, tested one by one
+    Leaf(Vec<(usize, Arc<dyn Hit>)>),
+    Node(Box<BvhNode>),
+}
+
+impl BvhChild {
+    fn new(
+        objects: Vec<(usize, Arc<dyn Hit>)>,
+        leaf_size: usize,
+        rng: &mut dyn RngCore,
+    ) -> BvhChild {
+        if objects.len() <= leaf_size {
+            BvhChild::Leaf(objects)
+        } else {
+            BvhChild::Node(Box::new(BvhNode::new(objects, leaf_size, rng)))
+        }
+    }
+
+    fn hit_indexed(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord)> {
+        match self {
+            BvhChild::Leaf(objects) => {
+                let mut closest = None;
+                for (idx, object) in objects {
+                    let closest_so_far = closest
+                        .as_ref()
+                        .map_or(t_max, |(_, rec): &(usize, HitRecord)| rec.t);
+                    stats::record(|stats| stats.hit_tests += 1);
+                    if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                        closest = Some((*idx, rec));
+                    }
+                }
+                closest
+            }
+            BvhChild::Node(node) => node.hit_indexed(r, t_min, t_max),
+        }
+    }
+
+    fn bbox(&self) -> Aabb {
+        match self {
+            BvhChild::Leaf(objects) => objects
+                .iter()
+                .map(|(_, object)| object.bounding_box().expect("BVH leaves are bounded"))
+                .reduce(surrounding_box)
+                .expect("BVH leaves are not empty"),
+            BvhChild::Node(node) => node.bbox,
+        }
+    }
+}
+
+impl BvhNode {
+    // Splits at the median along a random axis, ordered by box centroid, until at most
+    // `leaf_size` objects are left on a side. Every object must have a bounding box, and
+    // there must be at least one.
+    pub fn new(
+        mut objects: Vec<(usize, Arc<dyn Hit>)>,
+        leaf_size: usize,
+        rng: &mut dyn RngCore,
+    ) -> BvhNode {
+        let axis = rng.gen_range(0..3);
+        let centroid = |object: &Arc<dyn Hit>| {
+            let bbox = object.bounding_box().expect("BVH objects are bounded");
+            bbox.minimum[axis] + bbox.maximum[axis]
+        };
+
+        let (left, right) = match objects.len() {
+            0 => panic!("BVH over no objects"),
+            1 => (BvhChild::Leaf(objects.clone()), BvhChild::Leaf(objects)),
+            len => {
+                objects.sort_by(|(_, a), (_, b)| {
+                    centroid(a)
+                        .partial_cmp(&centroid(b))
+                        .unwrap_or(Ordering::Equal)
+                });
+                let upper = objects.split_off(len / 2);
+                (
+                    BvhChild::new(objects, leaf_size, rng),
+                    BvhChild::new(upper, leaf_size, rng),
+                )
+            }
+        };
+
+        BvhNode {
+            bbox: surrounding_box(left.bbox(), right.bbox()),
+            left,
+            right,
+        }
+    }
+
+    pub fn hit_indexed(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord)> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        // The right child only has to beat whatever the left one found
+        let left = self.left.hit_indexed(r, t_min, t_max);
+        let closest_so_far = left.as_ref().map_or(t_max, |(_, rec)| rec.t);
+        self.right.hit_indexed(r, t_min, closest_so_far).or(left)
+    }
+}
+
+impl Hit for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_indexed(r, t_min, t_max).map(|(_, rec)| rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hit::{World, WorldBuilder};
+    use crate::material::{Lambertian, Metal, Scatter};
+    use crate::render::{render, Settings};
+    use crate::sphere::Sphere;
+    use crate::vec::{Color, Point3, Vec3};
+
+    #[test]
+    fn hierarchy_renders_the_same_as_a_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let spheres: Vec<(Point3, f64, Arc<dyn Scatter>)> = (0..40)
+            .map(|i| {
+                let center = Point3::new(
+                    rng.gen_range(-3.0..3.0),
+                    rng.gen_range(-1.0..2.0),
+                    rng.gen_range(-6.0..-2.0),
+                );
+                let albedo = Color::new(rng.gen(), rng.gen(), rng.gen());
+                let mat: Arc<dyn Scatter> = if i % 3 == 0 {
+                    Arc::new(Metal::new(albedo, 0.1))
+                } else {
+                    Arc::new(Lambertian::new(albedo))
+                };
+                (center, rng.gen_range(0.1..0.6), mat)
+            })
+            .collect();
+
+        let hierarchy = spheres
+            .iter()
+            .fold(WorldBuilder::new(), |world, (center, radius, mat)| {
+                world.sphere(*center, *radius, mat.clone())
+            })
+            .build();
+        // Pushed objects bypass the hierarchy
+        let mut linear = World::default();
+        for (center, radius, mat) in &spheres {
+            linear.push(Sphere::new(*center, *radius, mat.clone()));
+        }
+
+        let cam = CameraBuilder::new().aspect_ratio(1.5).build();
+        let settings = Settings {
+            seed: Some(5),
+            ..Settings::new(24, 16, 4)
+        };
+        let bits = |world: &World| -> Vec<[u64; 3]> {
+            render(world, &cam, &settings)
+                .pixels
+                .iter()
+                .map(|pixel| {
+                    let color = pixel.color;
+                    [
+                        color.x().to_bits(),
+                        color.y().to_bits(),
+                        color.z().to_bits(),
+                    ]
+                })
+                .collect()
+        };
+        assert_eq!(bits(&hierarchy), bits(&linear));
+    }
+
+    #[test]
+    fn leaf_size_does_not_change_what_is_hit() {
+        let mut rng = StdRng::seed_from_u64(12);
+        let mat: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let spheres: Vec<(Point3, f64)> = (0..50)
+            .map(|_| {
+                let center = Point3::new(
+                    rng.gen_range(-3.0..3.0),
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-8.0..-2.0),
+                );
+                (center, rng.gen_range(0.1..0.5))
+            })
+            .collect();
+        let build = |leaf_size: usize| {
+            spheres
+                .iter()
+                .fold(
+                    WorldBuilder::new().bvh_leaf_size(leaf_size),
+                    |world, (center, radius)| world.sphere(*center, *radius, mat.clone()),
+                )
+                .build()
+        };
+
+        let worlds: Vec<World> = [1, 4, 8, 100].iter().map(|size| build(*size)).collect();
+        for i in 0..2000 {
+            let direction = Vec3::new(rng.gen_range(-0.6..0.6), rng.gen_range(-0.4..0.4), -1.0);
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), direction, 0.0);
+            let hits: Vec<Option<(usize, u64)>> = worlds
+                .iter()
+                .map(|world| {
+                    let idx = world.hit_index(&r, 0.001, f64::INFINITY)?;
+                    Some((idx, world.hit(&r, 0.001, f64::INFINITY)?.t.to_bits()))
+                })
+                .collect();
+            assert!(hits.iter().all(|hit| *hit == hits[0]), "ray {} differs", i);
+        }
+    }
+}
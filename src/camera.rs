@@ -1,17 +1,180 @@
+use rand::Rng;
+
 use super::ray::Ray;
 use super::vec::{Point3, Vec3};
 
+#[derive(Clone, Copy, PartialEq)]
+enum Projection {
+    // Rays fan out from the lens
+    Perspective,
+    // Parallel rays along the view direction, from all over the viewport
+    Orthographic,
+}
+
 pub struct Camera {
+    projection: Projection,
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
     cu: Vec3,
     cv: Vec3,
+    cw: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
+    // Shutter open/close times, each ray gets its own time in between
+    time0: f64,
+    time1: f64,
+}
+
+// Camera settings by name, so the many points, vectors and angles cannot be mixed up.
+// Unset, it looks from the origin down -z with +y up, a 90 degree field of view and an
+// aspect ratio of 1, with no defocus blur and the focus on lookat.
+pub struct CameraBuilder {
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    // Vertical field of view in degrees
+    vfov: f64,
+    aspect_ratio: f64,
+    aperture: f64,
+    // The distance to lookat when None
+    focus_dist: Option<f64>,
+    // Viewport height in world units of an orthographic camera
+    ortho: Option<f64>,
+    time0: f64,
+    time1: f64,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> CameraBuilder {
+        CameraBuilder {
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aspect_ratio: 1.0,
+            aperture: 0.0,
+            focus_dist: None,
+            ortho: None,
+            time0: 0.0,
+            time1: 0.0,
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn new() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    pub fn lookfrom(self, lookfrom: Point3) -> CameraBuilder {
+        CameraBuilder { lookfrom, ..self }
+    }
+
+    pub fn lookat(self, lookat: Point3) -> CameraBuilder {
+        CameraBuilder { lookat, ..self }
+    }
+
+    pub fn vup(self, vup: Vec3) -> CameraBuilder {
+        CameraBuilder { vup, ..self }
+    }
+
+    pub fn vfov(self, vfov: f64) -> CameraBuilder {
+        CameraBuilder { vfov, ..self }
+    }
+
+    pub fn aspect_ratio(self, aspect_ratio: f64) -> CameraBuilder {
+        CameraBuilder {
+            aspect_ratio,
+            ..self
+        }
+    }
+
+    pub fn aperture(self, aperture: f64) -> CameraBuilder {
+        CameraBuilder { aperture, ..self }
+    }
+
+    pub fn focus_dist(self, focus_dist: f64) -> CameraBuilder {
+        CameraBuilder {
+            focus_dist: Some(focus_dist),
+            ..self
+        }
+    }
+
+    // Parallel rays from a viewport `viewport_height` world units high, everything is in
+    // focus and vfov and aperture do not apply
+    pub fn orthographic(self, viewport_height: f64) -> CameraBuilder {
+        CameraBuilder {
+            ortho: Some(viewport_height),
+            ..self
+        }
+    }
+
+    pub fn shutter(self, open: f64, close: f64) -> CameraBuilder {
+        CameraBuilder {
+            time0: open,
+            time1: close,
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Camera {
+        let (lookfrom, lookat) = (self.lookfrom, self.lookat);
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (lookfrom - lookat).length());
+        let cw = (lookfrom - lookat).normalized();
+        let cu = self.vup.cross(cw).normalized();
+        let cv = cw.cross(cu);
+
+        match self.ortho {
+            Some(viewport_height) => {
+                let h = self.aspect_ratio * viewport_height * cu;
+                let v = viewport_height * cv;
+                Camera {
+                    projection: Projection::Orthographic,
+                    origin: lookfrom,
+                    horizontal: h,
+                    vertical: v,
+                    // The viewport lies in the plane through lookfrom
+                    lower_left_corner: lookfrom - h / 2.0 - v / 2.0,
+                    cu,
+                    cv,
+                    cw,
+                    lens_radius: 0.0,
+                    focus_dist,
+                    time0: self.time0,
+                    time1: self.time1,
+                }
+            }
+            None => {
+                let theta = std::f64::consts::PI / 180.0 * self.vfov;
+                let viewport_height: f64 = 2.0 * (theta / 2.0).tan();
+                let viewport_width: f64 = self.aspect_ratio * viewport_height;
+                let h = focus_dist * viewport_width * cu;
+                let v = focus_dist * viewport_height * cv;
+                Camera {
+                    projection: Projection::Perspective,
+                    origin: lookfrom,
+                    horizontal: h,
+                    vertical: v,
+                    lower_left_corner: lookfrom - h / 2.0 - v / 2.0 - focus_dist * cw,
+                    cu,
+                    cv,
+                    cw,
+                    lens_radius: self.aperture / 2.0,
+                    focus_dist,
+                    time0: self.time0,
+                    time1: self.time1,
+                }
+            }
+        }
+    }
 }
 
 impl Camera {
+    // The positional form of CameraBuilder, for callers written against it
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -21,36 +184,178 @@ impl Camera {
         aperture: f64,
         focus_dist: f64,
     ) -> Camera {
-        // Vertical field-of-view in degrees
-        let theta = std::f64::consts::PI / 180.0 * vfov;
-        let viewport_height: f64 = 2.0 * (theta / 2.0).tan();
-        let viewport_width: f64 = aspect_ratio * viewport_height;
-
-        let cw = (lookfrom - lookat).normalized();
-        let cu = vup.cross(cw).normalized();
-        let cv = cw.cross(cu);
-        let h = focus_dist * viewport_width * cu;
-        let v = focus_dist * viewport_height * cv;
-        let llc = lookfrom - h / 2.0 - v / 2.0 - focus_dist * cw;
+        CameraBuilder::new()
+            .lookfrom(lookfrom)
+            .lookat(lookat)
+            .vup(vup)
+            .vfov(vfov)
+            .aspect_ratio(aspect_ratio)
+            .aperture(aperture)
+            .focus_dist(focus_dist)
+            .build()
+    }
 
-        Camera {
-            origin: lookfrom,
-            horizontal: h,
-            vertical: v,
-            lower_left_corner: llc,
-            cu,
-            cv,
-            lens_radius: aperture / 2.0,
+    pub fn get_ray<R: Rng + ?Sized>(&self, s: f64, t: f64, rng: &mut R) -> Ray {
+        if self.projection == Projection::Orthographic {
+            let time = self.time0 + (self.time1 - self.time0) * rng.gen::<f64>();
+            let origin = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+            return Ray::new(origin, -self.cw, time);
         }
-    }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+        let rd = self.lens_radius * Vec3::random_in_unit_disk(rng);
         let offset = self.cu * rd.x() + self.cv * rd.y();
+        // Drawn per ray, so every sample of a pixel lands at its own time
+        let time = self.time0 + (self.time1 - self.time0) * rng.gen::<f64>();
 
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            time,
         )
     }
+
+    pub fn falloff(&self, r: &Ray) -> f64 {
+        // Natural cos^4 falloff from the angle between the ray and the view axis
+        let cos_theta = -r.direction().normalized().dot(self.cw);
+        cos_theta.powi(4)
+    }
+
+    // Components along the camera basis: right, up and back towards the viewer
+    pub fn to_camera_space(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.cu), v.dot(self.cv), v.dot(self.cw))
+    }
+
+    // Distance of a point from the focus plane along the view axis, relative to focus_dist
+    pub fn defocus(&self, p: Point3) -> f64 {
+        let depth = (p - self.origin).dot(-self.cw);
+        (depth - self.focus_dist).abs() / self.focus_dist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_same_rays(a: &Camera, b: &Camera) {
+        let (mut rng_a, mut rng_b) = (StdRng::seed_from_u64(1), StdRng::seed_from_u64(1));
+        for &(s, t) in &[(0.0, 0.0), (0.5, 0.5), (1.0, 0.25), (0.3, 0.9)] {
+            let (ra, rb) = (a.get_ray(s, t, &mut rng_a), b.get_ray(s, t, &mut rng_b));
+            assert!((ra.origin() - rb.origin()).length() < 1e-12);
+            assert!((ra.direction() - rb.direction()).length() < 1e-12);
+            assert_eq!(ra.time(), rb.time());
+        }
+    }
+
+    #[test]
+    fn builder_gives_the_rays_of_new() {
+        let (lookfrom, lookat) = (Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0));
+        let vup = Vec3::new(0.1, 1.0, 0.0);
+        let built = CameraBuilder::new()
+            .lookfrom(lookfrom)
+            .lookat(lookat)
+            .vup(vup)
+            .vfov(20.0)
+            .aspect_ratio(16.0 / 9.0)
+            .aperture(0.1)
+            .focus_dist(10.0)
+            .build();
+        let positional = Camera::new(lookfrom, lookat, vup, 20.0, 16.0 / 9.0, 0.1, 10.0);
+        assert_same_rays(&built, &positional);
+    }
+
+    #[test]
+    fn defaults_are_pinhole_focused_on_lookat_with_y_up() {
+        let (lookfrom, lookat) = (Point3::new(1.0, 2.0, 3.0), Point3::new(-2.0, 0.5, -1.0));
+        let defaults = CameraBuilder::new()
+            .lookfrom(lookfrom)
+            .lookat(lookat)
+            .vfov(40.0)
+            .build();
+        let focus_dist = (lookfrom - lookat).length();
+        let explicit = Camera::new(
+            lookfrom,
+            lookat,
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            focus_dist,
+        );
+        assert_same_rays(&defaults, &explicit);
+
+        // No aperture: every ray starts at lookfrom, whatever the lens sample
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..100 {
+            let r = defaults.get_ray(0.5, 0.5, &mut rng);
+            assert!((r.origin() - lookfrom).length() < 1e-12);
+        }
+        // In focus at lookat, and the top of the image is up
+        assert!(defaults.defocus(lookat) < 1e-12);
+        let (top, bottom) = (
+            defaults.get_ray(0.5, 1.0, &mut rng),
+            defaults.get_ray(0.5, 0.0, &mut rng),
+        );
+        assert!(top.direction().normalized().y() > bottom.direction().normalized().y());
+    }
+
+    #[test]
+    fn ray_times_are_uniform_over_the_shutter() {
+        let cam = CameraBuilder::new().shutter(2.0, 4.0).build();
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut buckets = [0; 10];
+        let mut repeats = 0;
+        let mut last = None;
+        for _ in 0..10_000 {
+            // Always the same pixel, as for the samples of one pixel
+            let time = cam.get_ray(0.5, 0.5, &mut rng).time();
+            assert!((2.0..4.0).contains(&time));
+            buckets[((time - 2.0) / 0.2) as usize] += 1;
+            repeats += (last == Some(time)) as u32;
+            last = Some(time);
+        }
+        // About 1000 each, a few standard deviations either way
+        assert!(
+            buckets.iter().all(|n| (850..1150).contains(n)),
+            "{:?}",
+            buckets
+        );
+        assert_eq!(repeats, 0);
+
+        // A static scene still gets times, the same for an orthographic camera
+        let ortho = CameraBuilder::new()
+            .orthographic(2.0)
+            .shutter(2.0, 4.0)
+            .build();
+        let time = ortho.get_ray(0.5, 0.5, &mut rng).time();
+        assert!((2.0..4.0).contains(&time));
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let lookfrom = Point3::new(0.0, 2.0, 5.0);
+        let cam = CameraBuilder::new()
+            .lookfrom(lookfrom)
+            .lookat(Point3::new(0.0, 2.0, 0.0))
+            .aspect_ratio(2.0)
+            // Ignored, there is no lens
+            .aperture(1.0)
+            .orthographic(3.0)
+            .build();
+        let mut rng = StdRng::seed_from_u64(4);
+        let (left, right) = (
+            cam.get_ray(0.25, 0.5, &mut rng),
+            cam.get_ray(0.75, 0.5, &mut rng),
+        );
+        let view = Vec3::new(0.0, 0.0, -1.0);
+        assert!((left.direction() - view).length() < 1e-12);
+        assert!((right.direction() - view).length() < 1e-12);
+        // Half the 6 unit wide viewport apart, in the plane through lookfrom
+        assert!((right.origin() - left.origin() - Vec3::new(3.0, 0.0, 0.0)).length() < 1e-12);
+        let center = cam.get_ray(0.5, 0.5, &mut rng);
+        assert!((center.origin() - lookfrom).length() < 1e-12);
+        let top = cam.get_ray(0.5, 1.0, &mut rng);
+        assert!((top.origin() - Point3::new(0.0, 3.5, 5.0)).length() < 1e-12);
+    }
 }
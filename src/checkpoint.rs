@@ -0,0 +1,179 @@
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+
+const MAGIC: &str = "RAYRUST-CHECKPOINT 2";
+
+// Per pixel sums of a render in progress, saved after every pass so an interrupted render can
+// carry on with --resume. Two text lines, the magic and
+// "width height seed options pass_samples passes samples" with "-" for no seed and the
+// options hash in hex, then five little-endian f64 per pixel: summed r, g, b, summed
+// coverage and summed squared luminance.
+pub struct Checkpoint {
+    pub width: u64,
+    pub height: u64,
+    pub seed: Option<u64>,
+    // Hash of the other settings that change the sums, which a resumed render must match
+    pub options: u64,
+    // Resumed renders keep the pass size, so they trace the same passes a single run would
+    pub pass_samples: u64,
+    pub passes: u64,
+    // Samples per pixel so far, the last pass may have been a short one
+    pub samples: u64,
+    pub sums: Vec<[f64; 5]>,
+}
+
+impl Checkpoint {
+    pub fn new(
+        width: u64,
+        height: u64,
+        seed: Option<u64>,
+        options: u64,
+        pass_samples: u64,
+    ) -> Checkpoint {
+        Checkpoint {
+            width,
+            height,
+            seed,
+            options,
+            pass_samples,
+            passes: 0,
+            samples: 0,
+            sums: vec![[0.0; 5]; (width * height) as usize],
+        }
+    }
+
+    // Through a temporary file renamed over the old checkpoint, so dying mid-write loses
+    // one pass at most
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        let seed = self
+            .seed
+            .map_or_else(|| "-".to_string(), |seed| seed.to_string());
+        writeln!(out, "{}", MAGIC)?;
+        writeln!(
+            out,
+            "{} {} {} {:016x} {} {} {}",
+            self.width,
+            self.height,
+            seed,
+            self.options,
+            self.pass_samples,
+            self.passes,
+            self.samples
+        )?;
+        for sum in self.sums.iter().flatten() {
+            out.write_all(&sum.to_le_bytes())?;
+        }
+        out.into_inner()?.sync_all()?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn load(path: &str) -> io::Result<Checkpoint> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        let mut parts = data.splitn(3, |&b| b == b'\n');
+        if parts.next() != Some(MAGIC.as_bytes()) {
+            return Err(invalid("not a rayrust checkpoint"));
+        }
+        let header = parts
+            .next()
+            .and_then(|header| std::str::from_utf8(header).ok())
+            .ok_or_else(|| invalid("truncated header"))?;
+        let number = |s: &str| {
+            s.parse::<u64>()
+                .map_err(|_| invalid(&format!("invalid number {:?} in header", s)))
+        };
+        let (width, height, seed, options, pass_samples, passes, samples) =
+            match header.split_whitespace().collect::<Vec<_>>()[..] {
+                [width, height, seed, options, pass_samples, passes, samples] => (
+                    number(width)?,
+                    number(height)?,
+                    match seed {
+                        "-" => None,
+                        seed => Some(number(seed)?),
+                    },
+                    u64::from_str_radix(options, 16)
+                        .map_err(|_| invalid(&format!("invalid options hash {:?}", options)))?,
+                    number(pass_samples)?,
+                    number(passes)?,
+                    number(samples)?,
+                ),
+                _ => return Err(invalid("expected 7 header fields")),
+            };
+
+        let body = parts.next().unwrap_or(&[]);
+        // A corrupt header must not overflow into a size that happens to match
+        let expected = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(5 * 8));
+        if expected != Some(body.len() as u64) {
+            return Err(invalid("pixel data does not match the image size"));
+        }
+        let values: Vec<f64> = body
+            .chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().expect("chunks of 8")))
+            .collect();
+        let sums = values
+            .chunks_exact(5)
+            .map(|sum| [sum[0], sum[1], sum[2], sum[3], sum[4]])
+            .collect();
+
+        Ok(Checkpoint {
+            width,
+            height,
+            seed,
+            options,
+            pass_samples,
+            passes,
+            samples,
+            sums,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rayrust-{}-{}", std::process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn saved_checkpoints_load_back() {
+        let mut checkpoint = Checkpoint::new(3, 2, Some(7), 0xdead_beef_0123_4567, 16);
+        checkpoint.passes = 2;
+        checkpoint.samples = 20;
+        checkpoint.sums[4] = [1.0, 2.0, 3.0, 0.5, 1.0e-300];
+        let path = temp_path("roundtrip");
+        checkpoint.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.seed), (3, 2, Some(7)));
+        assert_eq!(loaded.options, 0xdead_beef_0123_4567);
+        assert_eq!(
+            (loaded.pass_samples, loaded.passes, loaded.samples),
+            (16, 2, 20)
+        );
+        assert_eq!(loaded.sums[4], [1.0, 2.0, 3.0, 0.5, 1.0e-300]);
+    }
+
+    #[test]
+    fn sizes_that_overflow_are_rejected() {
+        let path = temp_path("overflow");
+        // 2^32 * 2^29 * 40 wraps to 0, which the empty body would match
+        let header = format!("{}\n4294967296 536870912 - 0 1 0 0\n", MAGIC);
+        fs::write(&path, header).unwrap();
+
+        let err = Checkpoint::load(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::material::Scatter;
+
+use super::hit::{Hit, HitRecord, World, WorldBuilder};
+use super::ray::Ray;
+use super::rect::{XyRect, XzRect, YzRect};
+use super::vec::Point3;
+
+// Axis-aligned box between two opposite corners, made of six rectangles
+pub struct Cube {
+    minimum: Point3,
+    maximum: Point3,
+    sides: World,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Cube {
+    pub fn new(p0: Point3, p1: Point3, mat: Arc<dyn Scatter>) -> Cube {
+        let minimum = Point3::new(p0.x().min(p1.x()), p0.y().min(p1.y()), p0.z().min(p1.z()));
+        let maximum = Point3::new(p0.x().max(p1.x()), p0.y().max(p1.y()), p0.z().max(p1.z()));
+        let (x0, y0, z0) = (minimum.x(), minimum.y(), minimum.z());
+        let (x1, y1, z1) = (maximum.x(), maximum.y(), maximum.z());
+        let sides = WorldBuilder::new()
+            .object(XyRect::new(x0, x1, y0, y1, z0, mat.clone()))
+            .object(XyRect::new(x0, x1, y0, y1, z1, mat.clone()))
+            .object(XzRect::new(x0, x1, z0, z1, y0, mat.clone()))
+            .object(XzRect::new(x0, x1, z0, z1, y1, mat.clone()))
+            .object(YzRect::new(y0, y1, z0, z1, x0, mat.clone()))
+            .object(YzRect::new(y0, y1, z0, z1, x1, mat.clone()))
+            .build();
+        Cube {
+            minimum,
+            maximum,
+            sides,
+            mat,
+        }
+    }
+}
+
+impl Hit for Cube {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.sides.hit(r, t_min, t_max)
+    }
+
+    // All six sides share it
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.minimum, self.maximum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::{Color, Vec3};
+
+    // Corners given in the wrong order on purpose
+    fn cube() -> Cube {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Cube::new(
+            Point3::new(1.0, 1.0, -2.0),
+            Point3::new(-1.0, -1.0, -4.0),
+            mat,
+        )
+    }
+
+    #[test]
+    fn hits_the_near_face() {
+        let r = Ray::new(Point3::new(0.2, -0.3, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = cube().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        // From the side, the +x face comes first
+        let r = Ray::new(Point3::new(5.0, 0.0, -3.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = cube().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn misses_beside_the_cube() {
+        let r = Ray::new(Point3::new(1.01, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(cube().hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+}
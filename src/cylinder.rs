@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::disk::Disk;
+use crate::material::Scatter;
+
+use super::hit::{Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Point3, Vec3};
+
+// Finite cylinder standing on the disk around `base`, reaching `height` along `axis`.
+// The ends are closed by disks unless it is made an open tube.
+pub struct Cylinder {
+    base: Point3,
+    axis: Vec3,
+    radius: f64,
+    height: f64,
+    // Reference direction across the axis, where u starts going around
+    across: Vec3,
+    caps: Option<[Disk; 2]>,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        mat: Arc<dyn Scatter>,
+    ) -> Cylinder {
+        let axis = axis.normalized();
+        // Any axis not too close to the cylinder's does for the cross product
+        let helper = if axis.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let caps = [
+            Disk::new(base, -axis, radius, mat.clone()),
+            Disk::new(base + height * axis, axis, radius, mat.clone()),
+        ];
+        Cylinder {
+            base,
+            axis,
+            radius,
+            height,
+            across: helper.cross(axis).normalized(),
+            caps: Some(caps),
+            mat,
+        }
+    }
+
+    // Without end caps, a tube that can be looked into
+    pub fn with_caps(self, caps: bool) -> Cylinder {
+        Cylinder {
+            caps: if caps { self.caps } else { None },
+            ..self
+        }
+    }
+
+    fn hit_side(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // The infinite cylinder is a circle in the plane across the axis, so drop the
+        // components along it and solve for the circle
+        let oc = r.origin() - self.base;
+        let direction = r.direction() - r.direction().dot(self.axis) * self.axis;
+        let offset = oc - oc.dot(self.axis) * self.axis;
+        let a = direction.length_squared();
+        if a < 1.0e-12 {
+            // Ray along the axis, it can only meet the caps
+            return None;
+        }
+        let half_b = offset.dot(direction);
+        let c = offset.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+
+        // The nearer root may fall past an end while the farther one is on the side inside
+        [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+            .iter()
+            .copied()
+            .filter(|t| (t_min..=t_max).contains(t))
+            .find_map(|t| {
+                let p = r.at(t);
+                let along = (p - self.base).dot(self.axis);
+                if !(0.0..=self.height).contains(&along) {
+                    return None;
+                }
+                let outward_normal = (p - self.base - along * self.axis) / self.radius;
+                let phi = outward_normal
+                    .dot(self.axis.cross(self.across))
+                    .atan2(outward_normal.dot(self.across));
+                let mut rec = HitRecord {
+                    t,
+                    p,
+                    mat: self.mat.clone(),
+                    normal: Vec3::new(0.0, 0.0, 0.0),
+                    u: (phi + PI) / (2.0 * PI),
+                    v: along / self.height,
+                    front_face: false,
+                };
+                rec.set_face_normal(r, outward_normal);
+                Some(rec)
+            })
+    }
+}
+
+impl Hit for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest = self.hit_side(r, t_min, t_max);
+        for cap in self.caps.iter().flatten() {
+            let closest_so_far = closest.as_ref().map_or(t_max, |rec| rec.t);
+            if let Some(rec) = cap.hit(r, t_min, closest_so_far) {
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    // The boxes of the two end circles, like for a disk
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut extent = Vec3::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            let sine = (1.0 - self.axis[axis].powi(2)).max(0.0).sqrt();
+            extent[axis] = self.radius * sine;
+        }
+        let top = self.base + self.height * self.axis;
+        Some(surrounding_box(
+            Aabb::new(self.base - extent, self.base + extent),
+            Aabb::new(top - extent, top + extent),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::Color;
+
+    // Radius 1 around the y axis, from y = 0 up to y = 2, centred at z = -5
+    fn upright() -> Cylinder {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Cylinder::new(
+            Point3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            2.0,
+            mat,
+        )
+    }
+
+    #[test]
+    fn hits_the_side() {
+        let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = upright().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((rec.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hits_the_top_cap_and_only_the_side_of_an_open_tube() {
+        let down = Ray::new(Point3::new(0.2, 6.0, -5.3), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = upright().hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        // Straight down an open tube there is nothing to hit
+        assert!(upright()
+            .with_caps(false)
+            .hit(&down, 0.001, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn misses_above_the_top() {
+        let r = Ray::new(Point3::new(0.0, 2.01, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(upright().hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use rand::RngCore;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::{Aabb, BOX_PADDING};
+use crate::material::Scatter;
+
+use super::hit::{planar_pdf_value, Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Point3, Vec3};
+
+// Flat round disk, facing along `normal`
+pub struct Disk {
+    center: Point3,
+    normal: Vec3,
+    radius: f64,
+    // In-plane axes, for texture coordinates and for picking points on the disk
+    u_axis: Vec3,
+    v_axis: Vec3,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mat: Arc<dyn Scatter>) -> Disk {
+        let normal = normal.normalized();
+        // Any axis not too close to the normal does for the first cross product
+        let helper = if normal.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u_axis = helper.cross(normal).normalized();
+        let v_axis = normal.cross(u_axis);
+        Disk {
+            center,
+            normal,
+            radius,
+            u_axis,
+            v_axis,
+            mat,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Hit for Disk {
+    // The plane first, then whether the point is within the radius of the center
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        const EPS: f64 = 1.0e-12;
+        let denom = self.normal.dot(r.direction());
+        if denom.abs() < EPS {
+            // Ray parallel to the disk's plane
+            return None;
+        }
+        let t = (self.center - r.origin()).dot(self.normal) / denom;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        let p = r.at(t);
+        let offset = p - self.center;
+        if offset.length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        // Polar coordinates: u around the rim, v out from the center
+        let phi = offset.dot(self.v_axis).atan2(offset.dot(self.u_axis));
+        let mut rec = HitRecord {
+            t,
+            p,
+            mat: self.mat.clone(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            u: (phi + PI) / (2.0 * PI),
+            v: offset.length() / self.radius,
+            front_face: false,
+        };
+        rec.set_face_normal(r, self.normal);
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Along each axis the rim reaches out radius times the sine of the normal's angle to it
+        let mut extent = Vec3::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            let sine = (1.0 - self.normal[axis].powi(2)).max(0.0).sqrt();
+            extent[axis] = self.radius * sine + BOX_PADDING;
+        }
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    // Uniform over the area, converted to solid angle as seen from `origin`
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        planar_pdf_value(self, self.normal, self.area(), origin, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let on_disk = Vec3::random_in_unit_disk(rng);
+        let point =
+            self.center + self.radius * (on_disk.x() * self.u_axis + on_disk.y() * self.v_axis);
+        (point - origin).normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::Color;
+
+    // Radius 1 around (0, 0, -2), tilted towards +y
+    fn tilted() -> Disk {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Disk::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            1.0,
+            mat,
+        )
+    }
+
+    #[test]
+    fn hits_inside_the_radius() {
+        let disk = tilted();
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = disk.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 1.0).normalized()).length() < 1e-12);
+
+        // Just inside the rim along the x axis, which lies in the disk's plane
+        let r = Ray::new(Point3::new(0.999, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = disk.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.v - 0.999).abs() < 1e-9);
+    }
+
+    #[test]
+    fn misses_just_outside_the_radius() {
+        let disk = tilted();
+        let r = Ray::new(Point3::new(1.001, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(disk.hit(&r, 0.001, f64::INFINITY).is_none());
+        assert_eq!(disk.pdf_value(r.origin(), r.direction()), 0.0);
+    }
+
+    #[test]
+    fn box_holds_the_rim() {
+        let disk = tilted();
+        let bbox = disk.bounding_box().unwrap();
+        for i in 0..16 {
+            let angle = i as f64 * PI / 8.0;
+            let rim = disk.center + angle.cos() * disk.u_axis + angle.sin() * disk.v_axis;
+            for axis in 0..3 {
+                assert!(bbox.minimum[axis] < rim[axis] && rim[axis] < bbox.maximum[axis]);
+            }
+        }
+    }
+}
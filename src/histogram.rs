@@ -0,0 +1,60 @@
+use super::vec::Color;
+
+// Bins are one stop (EV) wide, relative to display white (luminance 1.0)
+const MIN_STOP: i32 = -10;
+const MAX_STOP: i32 = 2;
+const BAR_WIDTH: usize = 50;
+
+pub struct Histogram {
+    scale: f64,
+    bins: Vec<usize>,
+    clipped: usize,
+    total: usize,
+}
+
+impl Histogram {
+    pub fn new(samples_per_pixel: u64) -> Histogram {
+        Histogram {
+            scale: 1.0 / samples_per_pixel as f64,
+            bins: vec![0; (MAX_STOP - MIN_STOP + 2) as usize],
+            clipped: 0,
+            total: 0,
+        }
+    }
+
+    pub fn add(&mut self, pixel: Color) {
+        let luminance = (pixel * self.scale).luminance();
+        // log2(0) is -inf and saturates to i32::MIN, which lands in the underflow bin
+        let stop = luminance.log2().floor() as i32;
+        let idx = stop.clamp(MIN_STOP - 1, MAX_STOP) - (MIN_STOP - 1);
+
+        self.bins[idx as usize] += 1;
+        if luminance >= 1.0 {
+            self.clipped += 1;
+        }
+        self.total += 1;
+    }
+
+    pub fn print(&self) {
+        let max_count = self.bins.iter().copied().max().unwrap_or(0).max(1);
+
+        eprintln!("Luminance histogram ({} pixels):", self.total);
+        for (idx, count) in self.bins.iter().enumerate() {
+            let stop = MIN_STOP - 1 + idx as i32;
+            let label = if stop < MIN_STOP {
+                format!("< {:+} EV", MIN_STOP)
+            } else if stop == MAX_STOP {
+                format!(">= {:+} EV", MAX_STOP)
+            } else {
+                format!("{:+} EV", stop)
+            };
+            let bar = "#".repeat(count * BAR_WIDTH / max_count);
+            eprintln!("{:>10} {:>9} {}", label, count, bar);
+        }
+
+        eprintln!(
+            "Clipped (luminance >= 1.0): {:.2}%",
+            100.0 * self.clipped as f64 / self.total.max(1) as f64
+        );
+    }
+}
@@ -1,20 +1,31 @@
+use std::f64::consts::PI;
+use std::io;
 use std::sync::Arc;
 
-use crate::material::Scatter;
+use crate::aabb::{surrounding_box, Aabb};
+use crate::bvh::BvhNode;
+use crate::material::{Dielectric, MaterialKind, Scatter, SplitDielectric};
+use crate::texture::{ImageTexture, Texture};
 
-use super::ray::Ray;
-use super::vec::{Point3, Vec3};
+use super::ray::{Ray, T_MAX, T_MIN};
+use super::sphere::Sphere;
+use super::stats;
+use super::vec::{Color, Point3, Vec3};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
 
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
     pub mat: Arc<dyn Scatter>,
     pub t: f64,
+    // Surface coordinates for textures, 0.0 where a primitive has no mapping
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
 impl HitRecord {
-    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) -> () {
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
             outward_normal
@@ -26,21 +37,420 @@ impl HitRecord {
 
 pub trait Hit: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    // Material of a simple primitive, None for objects made of several
+    fn material(&self) -> Option<&dyn Scatter> {
+        None
+    }
+
+    // None for unbounded objects
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // Density, per unit solid angle, of `random` picking `direction` from `origin`.
+    // Only objects that can be registered as lights need to implement these two.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // Unit direction from `origin` towards a random point on the object
+    fn random(&self, _origin: Point3, _rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+// pdf_value of a flat light whose `random` is uniform over its `area`: the density 1 / area
+// on the surface, as solid angle from `origin`, is distance^2 / (cos * area) where
+// `direction` hits the light, and zero where it misses
+pub fn planar_pdf_value(
+    light: &dyn Hit,
+    normal: Vec3,
+    area: f64,
+    origin: Point3,
+    direction: Vec3,
+) -> f64 {
+    match light.hit(&Ray::new(origin, direction, 0.0), T_MIN, T_MAX) {
+        Some(rec) => {
+            let distance_squared = rec.t.powi(2) * direction.length_squared();
+            let cosine = normal.dot(direction).abs() / direction.length();
+            distance_squared / (cosine * area)
+        }
+        None => 0.0,
+    }
+}
+
+// Radiance of rays that escape the scene
+#[derive(Clone, Default)]
+pub enum Background {
+    SolidColor(Color),
+    // White at the horizon blending into light blue overhead
+    #[default]
+    Gradient,
+    // Equirectangular image all around the scene, straight up along its top row
+    EnvironmentMap(Arc<dyn Texture>),
 }
 
-pub type World = Vec<Box<dyn Hit>>;
+impl Background {
+    // Lights the scene from a PPM panorama, or says why it cannot be loaded
+    pub fn environment(path: &str) -> io::Result<Background> {
+        ImageTexture::open(path).map(|map| Background::EnvironmentMap(Arc::new(map)))
+    }
+}
+
+// Where a direction lands on an equirectangular map: u goes around from the left edge behind
+// the default view, so -z is the middle column, and v goes from 0 straight down to 1 straight up
+pub fn equirect_uv(direction: Vec3) -> (f64, f64) {
+    let d = direction.normalized();
+    let phi = d.x().atan2(-d.z());
+    let theta = d.y().clamp(-1.0, 1.0).asin();
+    (0.5 + phi / (2.0 * PI), 0.5 + theta / PI)
+}
+
+#[derive(Default)]
+pub struct World {
+    objects: Vec<Arc<dyn Hit>>,
+    // Indices into `objects` of the emitters that are sampled directly
+    lights: Vec<usize>,
+    background: Background,
+    // Box around all objects, None if any of them is unbounded
+    bounds: Option<Aabb>,
+    // Hierarchy over the bounded objects built with the world. The unbounded ones, and any
+    // pushed afterwards, are scanned one by one.
+    bvh: Option<BvhNode>,
+    unbounded: Vec<usize>,
+}
+
+impl World {
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Hit> {
+        self.objects.iter().map(|object| object.as_ref())
+    }
+
+    pub fn lights(&self) -> impl Iterator<Item = &dyn Hit> {
+        self.lights
+            .iter()
+            .map(move |idx| self.objects[*idx].as_ref())
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    // Sphere around the world's box: its center and half its diagonal. None like the box,
+    // for an empty world or one with an unbounded object.
+    pub fn bounding_sphere(&self) -> Option<(Point3, f64)> {
+        let bbox = self.bounds?;
+        let center = 0.5 * (bbox.minimum + bbox.maximum);
+        Some((center, 0.5 * (bbox.maximum - bbox.minimum).length()))
+    }
+
+    // Adds an object to a built world, without rebuilding the hierarchy
+    pub fn push<H: Hit + 'static>(&mut self, object: H) {
+        let bbox = object.bounding_box();
+        self.bounds = if self.objects.is_empty() {
+            bbox
+        } else {
+            self.bounds.zip(bbox).map(|(a, b)| surrounding_box(a, b))
+        };
+        self.unbounded.push(self.objects.len());
+        self.objects.push(Arc::new(object));
+    }
+
+    // Index of the first object along the ray, to tell objects apart without shading
+    pub fn hit_index(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<usize> {
+        self.closest_hit(r, t_min, t_max).map(|(idx, _)| idx)
+    }
+
+    // Object whose bounding box the ray enters first, and where, ignoring the geometry inside.
+    // Unbounded objects have no box to hit.
+    pub fn first_box(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, f64)> {
+        let mut closest = None;
+        for (idx, object) in self.objects.iter().enumerate() {
+            let closest_so_far = closest.map_or(t_max, |(_, t)| t);
+            let entry = object
+                .bounding_box()
+                .and_then(|bbox| bbox.entry(r, t_min, closest_so_far));
+            if let Some(t) = entry {
+                closest = Some((idx, t));
+            }
+        }
+        closest
+    }
+
+    fn closest_hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord)> {
+        let mut closest = self
+            .bvh
+            .as_ref()
+            .and_then(|bvh| bvh.hit_indexed(r, t_min, t_max));
+
+        for idx in &self.unbounded {
+            let closest_so_far = closest.as_ref().map_or(t_max, |(_, rec)| rec.t);
+            stats::record(|stats| stats.hit_tests += 1);
+            if let Some(rec) = self.objects[*idx].hit(r, t_min, closest_so_far) {
+                closest = Some((*idx, rec));
+            }
+        }
+        closest
+    }
+}
 
 impl Hit for World {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut tmp_rec = None;
-        let mut closest_so_far = t_max;
+        self.closest_hit(r, t_min, t_max).map(|(_, rec)| rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+#[derive(Default)]
+pub struct WorldBuilder {
+    world: World,
+    cull_backfaces: bool,
+    split_glass: bool,
+    // Objects the hierarchy may keep in one leaf, unset is one
+    bvh_leaf_size: Option<usize>,
+}
 
-        for object in self {
-            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
-                closest_so_far = rec.t;
-                tmp_rec = Some(rec);
+impl WorldBuilder {
+    pub fn new() -> WorldBuilder {
+        WorldBuilder::default()
+    }
+
+    // Cull back faces of spheres that cannot be seen from inside, i.e. all but glass
+    pub fn cull_backfaces(self, cull_backfaces: bool) -> WorldBuilder {
+        WorldBuilder {
+            cull_backfaces,
+            ..self
+        }
+    }
+
+    // Make `glass` hand out SplitDielectric rather than the stochastic Dielectric
+    pub fn split_glass(self, split_glass: bool) -> WorldBuilder {
+        WorldBuilder {
+            split_glass,
+            ..self
+        }
+    }
+
+    // Stop splitting the hierarchy at this many objects, which trades depth for leaf scans
+    pub fn bvh_leaf_size(self, leaf_size: usize) -> WorldBuilder {
+        WorldBuilder {
+            bvh_leaf_size: Some(leaf_size.max(1)),
+            ..self
+        }
+    }
+
+    pub fn glass(&self, index_of_refraction: f64) -> Arc<dyn Scatter> {
+        if self.split_glass {
+            Arc::new(SplitDielectric::new(index_of_refraction))
+        } else {
+            Arc::new(Dielectric::new(index_of_refraction))
+        }
+    }
+
+    pub fn background(mut self, background: Background) -> WorldBuilder {
+        self.world.background = background;
+        self
+    }
+
+    pub fn object<H: Hit + 'static>(mut self, object: H) -> WorldBuilder {
+        self.world.objects.push(Arc::new(object));
+        self
+    }
+
+    // An emitter that diffuse surfaces sample directly. Once a scene has lights, diffuse
+    // bounces no longer pick up emission by chance, so every emitter should go through here.
+    pub fn light<H: Hit + 'static>(mut self, light: H) -> WorldBuilder {
+        self.world.lights.push(self.world.objects.len());
+        self.object(light)
+    }
+
+    pub fn sphere(self, center: Point3, radius: f64, mat: Arc<dyn Scatter>) -> WorldBuilder {
+        let cull = self.cull_backfaces && mat.kind() != MaterialKind::Dielectric;
+        self.object(Sphere::new(center, radius, mat).with_backface_culling(cull))
+    }
+
+    pub fn build(self) -> World {
+        // Unbounded as soon as one object is, and for an empty world
+        let mut boxes = self
+            .world
+            .objects
+            .iter()
+            .map(|object| object.bounding_box());
+        let bounds = boxes
+            .next()
+            .flatten()
+            .and_then(|first| boxes.try_fold(first, |acc, bbox| Some(surrounding_box(acc, bbox?))));
+
+        let (bounded, unbounded): (Vec<usize>, Vec<usize>) = (0..self.world.objects.len())
+            .partition(|idx| self.world.objects[*idx].bounding_box().is_some());
+        let bvh = (!bounded.is_empty()).then(|| {
+            let leaves = bounded
+                .into_iter()
+                .map(|idx| (idx, self.world.objects[idx].clone()))
+                .collect();
+            // Only the shape of the tree depends on it, keep that the same every run
+            BvhNode::new(
+                leaves,
+                self.bvh_leaf_size.unwrap_or(1),
+                &mut StdRng::seed_from_u64(0),
+            )
+        });
+
+        World {
+            bounds,
+            bvh,
+            unbounded,
+            ..self.world
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn builder_matches_a_hand_built_world() {
+        let red: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let grey: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let built = WorldBuilder::new()
+            .sphere(Point3::new(0.0, 0.0, -1.0), 0.5, red.clone())
+            .sphere(Point3::new(0.0, -100.5, -1.0), 100.0, grey.clone())
+            .build();
+        let mut pushed = World::default();
+        pushed.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, red));
+        pushed.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, grey));
+
+        let origin = Point3::new(0.0, 0.0, 1.0);
+        for i in 0..=20 {
+            for j in 0..=20 {
+                let direction = Vec3::new(i as f64 / 10.0 - 1.0, j as f64 / 10.0 - 1.0, -1.0);
+                let r = Ray::new(origin, direction, 0.0);
+                assert_eq!(
+                    built.hit_index(&r, 0.001, f64::INFINITY),
+                    pushed.hit_index(&r, 0.001, f64::INFINITY)
+                );
+                let (a, b) = (
+                    built.hit(&r, 0.001, f64::INFINITY),
+                    pushed.hit(&r, 0.001, f64::INFINITY),
+                );
+                assert_eq!(a.is_some(), b.is_some());
+                if let (Some(a), Some(b)) = (a, b) {
+                    assert_eq!(a.t.to_bits(), b.t.to_bits());
+                    assert!((a.normal - b.normal).length() < 1e-12);
+                }
             }
         }
-        tmp_rec
+
+        let (a, b) = (
+            built.bounding_box().unwrap(),
+            pushed.bounding_box().unwrap(),
+        );
+        assert!((a.minimum - b.minimum).length() < 1e-12);
+        assert!((a.maximum - b.maximum).length() < 1e-12);
+    }
+
+    fn assert_uv(direction: Vec3, u: f64, v: f64) {
+        let uv = equirect_uv(direction);
+        assert!(
+            (uv.0 - u).abs() < 1e-12 && (uv.1 - v).abs() < 1e-12,
+            "uv of {} is {:?}, expected ({}, {})",
+            direction,
+            uv,
+            u,
+            v
+        );
+    }
+
+    #[test]
+    fn equirect_uv_centres_the_default_view() {
+        assert_uv(Vec3::new(0.0, 0.0, -1.0), 0.5, 0.5);
+        assert_uv(Vec3::new(1.0, 0.0, 0.0), 0.75, 0.5);
+        assert_uv(Vec3::new(-2.0, 0.0, 0.0), 0.25, 0.5);
+        assert_uv(Vec3::new(0.0, 0.0, 1.0), 1.0, 0.5);
+        assert_uv(Vec3::new(0.0, 1.0, -1.0), 0.5, 0.75);
+        // Any u will do at the poles
+        assert_eq!(equirect_uv(Vec3::new(0.0, 3.0, 0.0)).1, 1.0);
+        assert_eq!(equirect_uv(Vec3::new(0.0, -1.0, 0.0)).1, 0.0);
+    }
+
+    #[test]
+    fn straight_up_samples_the_top_row() {
+        // 4x3 panorama: a white top row, black below
+        let path =
+            std::env::temp_dir().join(format!("rayrust-equirect-{}.ppm", std::process::id()));
+        std::fs::write(
+            &path,
+            "P3\n4 3\n255\n\
+             255 255 255  255 255 255  255 255 255  255 255 255\n\
+             0 0 0  0 0 0  0 0 0  0 0 0\n\
+             0 0 0  0 0 0  0 0 0  0 0 0\n",
+        )
+        .unwrap();
+        let background = Background::environment(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let map = match background {
+            Ok(Background::EnvironmentMap(map)) => map,
+            _ => panic!("the panorama did not load"),
+        };
+
+        let sample = |direction: Vec3| {
+            let (u, v) = equirect_uv(direction);
+            map.value(u, v, &Point3::new(0.0, 0.0, 0.0))
+        };
+        for direction in [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.1, 1.0, -0.1)].iter() {
+            assert_eq!(sample(*direction).y(), 1.0);
+        }
+        for direction in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0)].iter() {
+            assert_eq!(sample(*direction).y(), 0.0);
+        }
+    }
+
+    #[test]
+    fn bounding_sphere_holds_both_spheres() {
+        let grey: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let spheres = [
+            (Point3::new(-2.0, 0.5, 1.0), 0.5),
+            (Point3::new(3.0, 1.0, -4.0), 1.5),
+        ];
+        let world = spheres
+            .iter()
+            .fold(WorldBuilder::new(), |world, (center, radius)| {
+                world.sphere(*center, *radius, grey.clone())
+            })
+            .build();
+
+        let (center, radius) = world.bounding_sphere().unwrap();
+        for (sphere_center, sphere_radius) in spheres.iter() {
+            assert!((*sphere_center - center).length() + sphere_radius <= radius + 1e-12);
+        }
+        assert!(World::default().bounding_sphere().is_none());
+    }
+
+    #[test]
+    fn first_box_is_found_where_the_geometry_is_missed() {
+        let grey: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let world = WorldBuilder::new()
+            .sphere(Point3::new(0.0, 0.0, -5.0), 1.0, grey.clone())
+            .sphere(Point3::new(0.0, 0.0, -10.0), 3.0, grey)
+            .build();
+
+        // Through the corner of the near sphere's box, past the sphere itself
+        let corner = Ray::new(Point3::new(0.9, 0.9, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(world.hit(&corner, 0.001, f64::INFINITY).is_some());
+        assert_eq!(world.hit_index(&corner, 0.001, f64::INFINITY), Some(1));
+        assert_eq!(
+            world.first_box(&corner, 0.001, f64::INFINITY),
+            Some((0, 4.0))
+        );
+
+        let beside = Ray::new(Point3::new(3.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert_eq!(world.first_box(&beside, 0.001, f64::INFINITY), None);
     }
 }
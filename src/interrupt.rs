@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set by the first Ctrl-C, the render stops taking samples and writes what it has
+static STOP: AtomicBool = AtomicBool::new(false);
+
+pub fn install() {
+    let handler = on_sigint as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+pub fn requested() -> bool {
+    STOP.load(Ordering::Relaxed)
+}
+
+// Only async-signal-safe calls in here, so a second Ctrl-C leaves through _exit
+extern "C" fn on_sigint(_signal: libc::c_int) {
+    if STOP.swap(true, Ordering::Relaxed) {
+        unsafe { libc::_exit(130) }
+    }
+}
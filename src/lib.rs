@@ -0,0 +1,36 @@
+pub mod aabb;
+mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod cube;
+pub mod cylinder;
+pub mod disk;
+pub mod histogram;
+pub mod hit;
+pub mod interrupt;
+pub mod json;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod output;
+pub mod perlin;
+pub mod postprocess;
+mod progress;
+pub mod quad;
+pub mod ray;
+pub mod rect;
+pub mod render;
+pub mod scene;
+pub mod scenes;
+pub mod sphere;
+pub mod stats;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
+pub mod vec;
+
+// Build a World and a Camera and hand them to render() with the Settings. The rayrust
+// binary is a command line over the same calls.
+pub use render::{render, Image, Settings};
+// Custom materials implement this and go into a world like the built-in ones
+pub use material::Scatter as Material;
@@ -1,160 +1,1219 @@
-mod camera;
-mod hit;
-mod material;
-mod ray;
-mod sphere;
-mod vec;
-
-use rayon::prelude::*;
+use rand::RngCore;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 
-use camera::Camera;
-use hit::{Hit, World};
-use rand::{thread_rng, Rng};
-use ray::Ray;
-use sphere::Sphere;
-use vec::{Color, Point3};
-
-use crate::{
-    material::{Dielectric, Lambertian, Metal},
-    vec::Vec3,
+use rayrust::camera::CameraBuilder;
+use rayrust::checkpoint::Checkpoint;
+use rayrust::histogram::Histogram;
+use rayrust::hit::{Background, World, WorldBuilder};
+use rayrust::material::{self, Lambertian};
+use rayrust::output::{self, ImageWriter};
+use rayrust::postprocess::ToneMap;
+use rayrust::render::{
+    self, auto_max_depth, heatmap, specular_shares, stream_rng, EdgeDetect, Mode, NormalSpace,
+    Pixel, Renderer, Sampling, Settings,
 };
+use rayrust::scene::{self, BackgroundDesc, CameraSettings, SceneFile};
+use rayrust::scenes::{self, MaterialMix};
+use rayrust::transform::{RotateY, Translate};
+use rayrust::vec::{Color, Point3, Vec3};
+use rayrust::{interrupt, mesh, stats};
+
+// Scatter samples per material for --energy-check
+const ENERGY_CHECK_SAMPLES: u64 = 10_000;
+// Field of view from a light in degrees, wide to take in what it lights
+const LIGHT_VIEW_VFOV: f64 = 90.0;
+
+struct Options {
+    // Makes renders reproducible, fresh entropy every run without it
+    seed: Option<u64>,
+    // Unset falls back to the scene file, then to the defaults
+    width: Option<u64>,
+    // Height follows from the width
+    aspect: Option<AspectRatio>,
+    samples: Option<u64>,
+    sampling: Sampling,
+    // Luminance histogram on stderr once the image is done, one per frame under --orbit
+    histogram: bool,
+    // Write each scanline as soon as it is rendered instead of keeping the whole frame
+    stream: bool,
+    // Fight shadow acne by offsetting the ray origin along the normal instead of a t_min clamp
+    normal_offset: bool,
+    // Exponent applied to the cos^4 lens falloff, 0 disables vignetting
+    vignette: f64,
+    scene: String,
+    // Cells of the random scene's grid run from -random_extent to random_extent on both axes
+    random_extent: i32,
+    mode: Mode,
+    normal_space: NormalSpace,
+    // Output RGBA with alpha set to the fraction of samples that hit geometry
+    alpha: bool,
+    // Shade metals as Lambertians of the same albedo for judging forms
+    matte_metals: bool,
+    material_stats: bool,
+    // Ray and hit test counts and the ray throughput, printed at the end
+    stats: bool,
+    max_depth: MaxDepth,
+    shutter_open: f64,
+    shutter_close: f64,
+    cull_backfaces: bool,
+    // Objects a BVH leaf may hold before it is split further
+    bvh_leaf_size: usize,
+    // Orthographic projection with a viewport this many world units high
+    ortho: Option<f64>,
+    // Camera of the scene file to render through, its first one when unset
+    camera: Option<String>,
+    // Render through every camera of the scene file, one output file each
+    all_cameras: bool,
+    // Render from the light of this index towards the middle of the scene, in a debug mode
+    light_view: Option<usize>,
+    // Number of turntable frames to render around lookat
+    orbit: Option<u64>,
+    // Degrees the turntable turns over all frames
+    orbit_sweep: f64,
+    // End paths at random once they carry little light, unbiased but noisier per sample
+    russian_roulette: bool,
+    // Leave lights to be found by chance bounces, to compare against next-event estimation
+    no_light_sampling: bool,
+    // Upper bound on light arriving from the second bounce on, to tame fireflies
+    indirect_clamp: Option<f64>,
+    // Supersample only edge pixels on top of a cheaper base pass
+    edge_aa: Option<EdgeDetect>,
+    // Grayscale image of the per-sample luminance variance
+    variance_out: Option<String>,
+    // Stop sampling a pixel once the standard error of its gamma encoded luminance is this low
+    tolerance: Option<f64>,
+    // Samples every pixel starts with under --tolerance, and the batches it grows by
+    min_samples: u64,
+    // Heatmap of the samples each pixel took under --tolerance, blue for few and red for all
+    samples_out: Option<String>,
+    // Build glass as SplitDielectric, tracing reflection and refraction both
+    split_glass: bool,
+    // Applied to the averaged radiance before gamma encoding for 8-bit outputs
+    tonemap: ToneMap,
+    gamma: f64,
+    // Netpbm image wrapped around the globe of the globe and spin scenes
+    texture: Option<String>,
+    // Wavefront OBJ file whose triangles are added to the scene as they are
+    mesh: Option<String>,
+    // Placement of the mesh, turned about its y axis in degrees and then moved
+    mesh_rotate: f64,
+    mesh_offset: Vec3,
+    // Files to write the finished frame to, stdout when empty
+    outputs: Vec<String>,
+    // Overrides the scene's background when given
+    background: Option<BackgroundDesc>,
+    // Color of the lower hemisphere of the default sky
+    ground_color: Option<Color>,
+    // Report materials that reflect more light than they receive
+    energy_check: bool,
+    // Render in passes of checkpoint_every samples, saving the running sums after each
+    checkpoint: Option<String>,
+    checkpoint_every: u64,
+    // Carry on from a checkpoint, saving further passes back to it unless --checkpoint is given
+    resume: Option<String>,
+    // Size of the rayon pool, every core when unset
+    threads: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum MaxDepth {
+    // Picked from the scene's material mix
+    Auto,
+    Fixed(u64),
+}
 
-fn ray_color(r: &Ray, world: &World, depth: u64) -> Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
+impl FromStr for MaxDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MaxDepth, String> {
+        match s {
+            "auto" => Ok(MaxDepth::Auto),
+            _ => s
+                .parse()
+                .map(MaxDepth::Fixed)
+                .map_err(|err| format!("invalid max depth {:?}: {}", s, err)),
+        }
     }
+}
 
-    if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-        if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-            attenuation * ray_color(&scattered, world, depth - 1)
+// Width over height, given as "16:9" or as a number
+#[derive(Clone, Copy)]
+struct AspectRatio(f64);
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AspectRatio, String> {
+        let ratio = match s.split_once(':') {
+            Some((w, h)) => w.parse::<f64>().and_then(|w| Ok(w / h.parse::<f64>()?)),
+            None => s.parse(),
+        }
+        .map_err(|err| format!("invalid aspect ratio {:?}: {}", s, err))?;
+        if ratio.is_finite() && ratio > 0.0 {
+            Ok(AspectRatio(ratio))
         } else {
-            Color::new(0.0, 0.0, 0.0)
+            Err(format!("invalid aspect ratio {:?}: must be positive", s))
         }
-    } else {
-        let unit_direction = r.direction().normalized();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
     }
 }
 
-fn random_scene() -> World {
-    let mut rng = thread_rng();
-    let mut world = World::new();
-
-    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    let sphere_ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground);
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    // Display referred: averaged, tone mapped and gamma corrected to 8 bits
+    Ppm,
+    Pam,
+    Png,
+    // Scene referred: the averaged linear radiance as 32-bit floats, or as shared exponent RGBE
+    Pfm,
+    Hdr,
+}
 
-    world.push(Box::new(sphere_ground));
+impl OutputFormat {
+    fn from_path(path: &str) -> Option<OutputFormat> {
+        match path.rsplit('.').next()?.to_lowercase().as_str() {
+            "ppm" => Some(OutputFormat::Ppm),
+            "pam" => Some(OutputFormat::Pam),
+            "png" => Some(OutputFormat::Png),
+            "pfm" => Some(OutputFormat::Pfm),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+}
 
-    for a in -11..=11 {
-        for b in -11..=11 {
-            let choose_mat: f64 = rng.gen();
-            let center = Point3::new(
-                (a as f64) + rng.gen_range(0.0..0.9),
-                0.2,
-                (b as f64) + rng.gen_range(0.0..0.9),
-            );
+impl Options {
+    fn from_args() -> Options {
+        let args = Args::new(std::env::args().skip(1).collect(), std::env::vars_os());
+        Options::parse(&args).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            process::exit(2);
+        })
+    }
 
-            if choose_mat < 0.8 {
-                // Diffuse
-                let albedo = Color::random(0.0..1.0) * Color::random(0.0..1.0);
-                let sphere_mat = Arc::new(Lambertian::new(albedo));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere))
-            } else if choose_mat < 0.95 {
-                // Metal
-                let albedo = Color::random(0.4..1.0);
-                let fuzz = rng.gen_range(0.0..0.5);
-                let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.push(Box::new(sphere))
+    fn parse(args: &Args) -> Result<Options, String> {
+        let light_view = args.value("--light-view");
+        let options = Options {
+            seed: args.value("--seed"),
+            width: args.value("--width"),
+            aspect: args.value("--aspect"),
+            samples: args.value("--samples"),
+            sampling: args.value("--sampling").unwrap_or(Sampling::Random),
+            histogram: args.flag("--histogram"),
+            stream: args.flag("--stream"),
+            normal_offset: args.flag("--normal-offset"),
+            vignette: args.value("--vignette").unwrap_or(0.0),
+            scene: args
+                .value("--scene")
+                .unwrap_or_else(|| "random".to_string()),
+            random_extent: args.value("--random-extent").unwrap_or(11),
+            // Seen from a light, normals show what it lights and what it cannot see
+            mode: args.value("--mode").unwrap_or(if light_view.is_some() {
+                Mode::Normals
             } else {
-                // Glass
-                let sphere_mat = Arc::new(Dielectric::new(1.5));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
+                Mode::Beauty
+            }),
+            normal_space: args.value("--normal-space").unwrap_or(NormalSpace::World),
+            alpha: args.flag("--alpha"),
+            matte_metals: args.flag("--matte-metals"),
+            material_stats: args.flag("--material-stats"),
+            stats: args.flag("--stats"),
+            max_depth: args.value("--max-depth").unwrap_or(MaxDepth::Fixed(50)),
+            shutter_open: args.value("--shutter-open").unwrap_or(0.0),
+            shutter_close: args.value("--shutter-close").unwrap_or(1.0),
+            cull_backfaces: args.flag("--cull-backfaces"),
+            bvh_leaf_size: args.value("--bvh-leaf-size").unwrap_or(1),
+            ortho: args.value("--ortho"),
+            camera: args.value("--camera"),
+            all_cameras: args.flag("--all-cameras"),
+            light_view,
+            orbit: args.value("--orbit"),
+            orbit_sweep: args.value("--orbit-sweep").unwrap_or(360.0),
+            russian_roulette: args.flag("--russian-roulette"),
+            no_light_sampling: args.flag("--no-light-sampling"),
+            indirect_clamp: args.value("--indirect-clamp"),
+            edge_aa: match (args.flag("--edge-aa"), args.flag("--coverage-aa")) {
+                (true, true) => {
+                    return Err("--edge-aa and --coverage-aa are mutually exclusive".to_string())
+                }
+                (true, false) => Some(EdgeDetect::Luminance),
+                (false, true) => Some(EdgeDetect::Coverage),
+                (false, false) => None,
+            },
+            variance_out: args.value("--variance-out"),
+            tolerance: args.value("--tolerance"),
+            min_samples: args.value("--min-samples").unwrap_or(16),
+            samples_out: args.value("--samples-out"),
+            split_glass: args.flag("--split-glass"),
+            // --white-point W is extended Reinhard with W mapping to white
+            tonemap: match (args.value("--tonemap"), args.value("--white-point")) {
+                (Some(_), Some(_)) => {
+                    return Err("--tonemap and --white-point are mutually exclusive".to_string())
+                }
+                (_, Some(white)) => ToneMap::ReinhardExtended(white),
+                (tonemap, None) => tonemap.unwrap_or(ToneMap::Clamp),
+            },
+            gamma: args.value("--gamma").unwrap_or(2.0),
+            texture: args.value("--texture"),
+            mesh: args.value("--mesh"),
+            mesh_rotate: args.value("--mesh-rotate").unwrap_or(0.0),
+            mesh_offset: args
+                .value("--mesh-offset")
+                .unwrap_or(Vec3::new(0.0, 0.0, 0.0)),
+            outputs: args.values("--output"),
+            background: args.value("--background"),
+            ground_color: args.value("--ground-color"),
+            energy_check: args.flag("--energy-check"),
+            checkpoint: args.value("--checkpoint"),
+            checkpoint_every: args.value("--checkpoint-every").unwrap_or(16),
+            resume: args.value("--resume"),
+            threads: args.value("--threads"),
+        };
+        args.check()?;
+        Ok(options)
+    }
+}
+
+// The command line as Options reads it, over the RAYRUST_* environment. Every name looked up
+// is remembered, so whatever is left over afterwards can be reported.
+struct Args {
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    // Names looked up so far, and whether each takes a value
+    known: RefCell<Vec<(&'static str, bool)>>,
+    // Values that did not parse, read as unset until check reports them
+    errors: RefCell<Vec<String>>,
+}
+
+impl Args {
+    // Variables that are not valid unicode are left out, as if unset
+    fn new(args: Vec<String>, env: impl IntoIterator<Item = (OsString, OsString)>) -> Args {
+        let env = env
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        Args {
+            args,
+            env,
+            known: RefCell::new(Vec::new()),
+            errors: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn flag(&self, name: &'static str) -> bool {
+        self.known.borrow_mut().push((name, false));
+        self.args.iter().any(|arg| arg == name)
+            || self
+                .env
+                .get(&env_var(name))
+                .is_some_and(|value| !value.is_empty() && value != "0")
+    }
+
+    fn value<T: FromStr>(&self, name: &'static str) -> Option<T> {
+        self.known.borrow_mut().push((name, true));
+        self.parse_value(&self.args, name)
+    }
 
-                world.push(Box::new(sphere))
+    // Every value of an option that may be given more than once
+    fn values<T: FromStr>(&self, name: &'static str) -> Vec<T> {
+        self.known.borrow_mut().push((name, true));
+        let args = &self.args;
+        let given: Vec<usize> = (0..args.len()).filter(|idx| args[*idx] == name).collect();
+        if given.is_empty() {
+            return self.parse_value(args, name).into_iter().collect();
+        }
+        given
+            .into_iter()
+            .filter_map(|idx| self.parse_value(&args[idx..], name))
+            .collect()
+    }
+
+    // Options missing from the command line are looked up in the environment, for batch jobs
+    fn parse_value<T: FromStr>(&self, args: &[String], name: &str) -> Option<T> {
+        let (source, value) = match args.iter().position(|arg| arg == name) {
+            Some(idx) => (name.to_string(), args.get(idx + 1).cloned()),
+            None => {
+                let var = env_var(name);
+                let value = self.env.get(&var)?.clone();
+                (var, Some(value))
             }
+        };
+        let parsed = value.and_then(|value| value.parse().ok());
+        if parsed.is_none() {
+            let err = format!("{} expects a valid value", source);
+            self.errors.borrow_mut().push(err);
+        }
+        parsed
+    }
+
+    // The first value that did not parse, or else the first argument that is neither a known
+    // option nor the value of one
+    fn check(&self) -> Result<(), String> {
+        if let Some(err) = self.errors.borrow().first() {
+            return Err(err.clone());
+        }
+        let known = self.known.borrow();
+        let mut args = self.args.iter();
+        while let Some(arg) = args.next() {
+            match known.iter().find(|(name, _)| name == arg) {
+                Some((_, true)) => {
+                    args.next();
+                }
+                Some((_, false)) => {}
+                None if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+                None => return Err(format!("unexpected argument {:?}", arg)),
+            }
+        }
+        Ok(())
+    }
+}
+
+// "shot.png" with the suffix "front" is "shot_front.png"
+fn suffixed(path: &str, suffix: &str) -> String {
+    let file_start = path.rfind('/').map_or(0, |idx| idx + 1);
+    match path[file_start..].rfind('.') {
+        Some(dot) => format!(
+            "{}_{}{}",
+            &path[..file_start + dot],
+            suffix,
+            &path[file_start + dot..]
+        ),
+        None => format!("{}_{}", path, suffix),
+    }
+}
+
+// --max-depth is read from RAYRUST_MAX_DEPTH
+fn env_var(name: &str) -> String {
+    format!(
+        "RAYRUST_{}",
+        name.trim_start_matches("--")
+            .replace('-', "_")
+            .to_uppercase()
+    )
+}
+
+fn check_energy(world: &World, rng: &mut dyn RngCore) {
+    let mut checked = 0;
+    let mut highest: f64 = 0.0;
+    for (idx, object) in world.iter().enumerate() {
+        let mat = match object.material() {
+            Some(mat) => mat,
+            None => continue,
+        };
+        let albedo = material::material_albedo_estimate(mat, rng, ENERGY_CHECK_SAMPLES);
+        if albedo.max_component() > 1.0 + 1.0e-9 {
+            eprintln!(
+                "Energy check: object {} gains energy, albedo {}",
+                idx, albedo
+            );
         }
+        highest = highest.max(albedo.max_component());
+        checked += 1;
     }
+    eprintln!(
+        "Energy check: {} materials, highest albedo {:.4}",
+        checked, highest
+    );
+}
 
-    let mat1 = Arc::new(Dielectric::new(1.5));
-    let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+// A wide view from light `idx` towards the middle of the scene. It starts just outside the
+// light's box on that side, so the light itself does not fill the frame.
+fn light_view(world: &World, idx: usize) -> Result<CameraSettings, String> {
+    let count = world.lights().count();
+    let light = world.lights().nth(idx).ok_or_else(|| match count {
+        0 => "--light-view needs a scene with lights".to_string(),
+        _ => format!("--light-view {} is past the scene's {} lights", idx, count),
+    })?;
+    let bbox = light
+        .bounding_box()
+        .ok_or_else(|| format!("light {} has no bounding box to place the view at", idx))?;
+    let position = 0.5 * (bbox.minimum + bbox.maximum);
+    let lookat = world
+        .bounding_sphere()
+        .map_or(Point3::new(0.0, 0.0, 0.0), |(center, _)| center);
+    let toward = (lookat - position).normalized();
+    if !toward.x().is_finite() {
+        return Err(format!("light {} sits in the middle of the scene", idx));
+    }
+    let lookfrom = position + 0.5 * (bbox.maximum - bbox.minimum).length() * toward;
+    Ok(CameraSettings {
+        lookfrom,
+        lookat,
+        // Straight up or down, any other up will do
+        vup: if toward.y().abs() > 0.99 {
+            Vec3::new(0.0, 0.0, -1.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        },
+        vfov: LIGHT_VIEW_VFOV,
+        aperture: 0.0,
+        focus_dist: (lookat - lookfrom).length(),
+    })
+}
 
-    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat3);
-    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
-    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat1);
+// An environment map that cannot be read leaves the gradient in its place
+fn load_background(background: &BackgroundDesc) -> Background {
+    background.load().unwrap_or_else(|err| {
+        eprintln!("warning: {}, using the gradient background instead", err);
+        Background::Gradient
+    })
+}
 
-    world.push(Box::new(sphere1));
-    world.push(Box::new(sphere2));
-    world.push(Box::new(sphere3));
+// Hash of what changes the sums a checkpoint holds, besides the size and seed it records
+// itself. Resuming with any of it changed would add up samples of two different renders.
+// Files are known by name only, a scene file or texture edited in between goes unnoticed.
+fn render_hash(opts: &Options, settings: &Settings, view: &CameraSettings) -> u64 {
+    let background = match &settings.background {
+        None => "scene".to_string(),
+        Some(Background::Gradient) => "gradient".to_string(),
+        Some(Background::SolidColor(color)) => color.to_string(),
+        Some(Background::EnvironmentMap(_)) => "map".to_string(),
+    };
+    let description = format!(
+        "{} {} {:?} {:?} {} {} {} {} | {} {} {} {} {} {} {} {} {} {} {:?} {} {:?} | {} {} {} {} {} {} {:?} {} {}",
+        opts.scene,
+        opts.random_extent,
+        opts.texture,
+        opts.mesh,
+        opts.mesh_rotate,
+        opts.mesh_offset,
+        opts.split_glass,
+        opts.cull_backfaces,
+        settings.max_depth,
+        settings.sampling as u8,
+        settings.normal_offset,
+        settings.vignette,
+        settings.mode as u8,
+        settings.normal_space as u8,
+        settings.alpha,
+        settings.matte_metals,
+        settings.russian_roulette,
+        settings.no_light_sampling,
+        settings.indirect_clamp,
+        background,
+        settings.ground_color.map(|color| color.to_string()),
+        view.lookfrom,
+        view.lookat,
+        view.vup,
+        view.vfov,
+        view.aperture,
+        view.focus_dist,
+        opts.ortho,
+        opts.shutter_open,
+        opts.shutter_close,
+    );
+    // FNV-1a, which unlike std's hashers is fixed across Rust releases
+    description
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+        })
+}
 
-    world
+// One of the scenes defined in code, by --scene name
+fn builtin_scene(opts: &Options, builder: WorldBuilder) -> World {
+    match opts.scene.as_str() {
+        "random" => scenes::random_scene(builder, opts.random_extent, false, opts.seed),
+        "bouncing" => scenes::random_scene(builder, opts.random_extent, true, opts.seed),
+        "dome" => scenes::dome_scene(builder),
+        "lamp" => scenes::lamp_scene(builder),
+        "disk" => scenes::disk_scene(builder),
+        "cylinders" => scenes::cylinders_scene(builder),
+        "quads" => scenes::quads_scene(builder),
+        "penumbra" => scenes::penumbra_scene(builder),
+        "contact" => scenes::contact_scene(builder),
+        "catcher" => scenes::catcher_scene(builder),
+        "presets" => scenes::presets_scene(builder),
+        "floating" => scenes::floating_scene(builder),
+        "pyramid" => scenes::pyramid_scene(builder),
+        "cornell" => scenes::cornell_scene(builder),
+        "smoke" => scenes::smoke_scene(builder),
+        "blocks" => scenes::blocks_scene(builder),
+        "marble" => scenes::marble_scene(builder, &mut stream_rng(opts.seed, 0)),
+        "globe" => scenes::globe_scene(builder, opts.texture.as_deref().unwrap_or("earthmap.ppm")),
+        "spin" => scenes::spin_scene(builder, opts.texture.as_deref().unwrap_or("earthmap.ppm")),
+        "grid" => {
+            let mix = MaterialMix {
+                diffuse: 0.6,
+                metal: 0.3,
+                glass: 0.1,
+            };
+            scenes::sphere_grid_scene(builder, 10, 0.4, mix)
+        }
+        name => {
+            eprintln!("error: unknown scene {:?}", name);
+            process::exit(2);
+        }
+    }
 }
 
-fn main() {
+fn main() -> io::Result<()> {
+    let opts = Options::from_args();
+    interrupt::install();
+    if let Some(threads) = opts.threads {
+        if threads == 0 {
+            eprintln!("error: --threads must be at least 1");
+            process::exit(2);
+        }
+        // The global pool, so the scene setup runs on it as well as the render
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap_or_else(|err| {
+                eprintln!("error: cannot start {} threads: {}", threads, err);
+                process::exit(2);
+            });
+    }
+    // Any other --scene value names a built-in scene
+    let scene_file = opts.scene.ends_with(".json").then(|| {
+        SceneFile::load(&opts.scene).unwrap_or_else(|err| {
+            eprintln!("error: cannot load scene {:?}: {}", opts.scene, err);
+            process::exit(2);
+        })
+    });
+    let image_width = opts
+        .width
+        .or_else(|| scene_file.as_ref()?.width)
+        .unwrap_or(1200);
+    let samples = opts
+        .samples
+        .or_else(|| scene_file.as_ref()?.samples)
+        .unwrap_or(500);
+    // Pixel coordinates are divided by width - 1 and height - 1
+    if image_width < 2 {
+        eprintln!("error: --width must be at least 2");
+        process::exit(2);
+    }
+    if samples == 0 {
+        eprintln!("error: --samples must be positive");
+        process::exit(2);
+    }
+
     // Image
-    const ASPECT_RATIO: f64 = 16.0 / 9.0;
-    const IMAGE_WIGHT: u64 = 1200;
-    const IMAGE_HEIGHT: u64 = (IMAGE_WIGHT as f64 / ASPECT_RATIO) as u64;
-    const SAMPLES_PER_PIXEL: u64 = 500;
-    const MAX_DEPTH: u64 = 50;
+    let aspect_ratio = opts
+        .aspect
+        .map(|aspect| aspect.0)
+        .or_else(|| scene_file.as_ref()?.aspect)
+        .unwrap_or(16.0 / 9.0);
+    let image_height = (image_width as f64 / aspect_ratio) as u64;
+    if image_height < 2 {
+        eprintln!("error: --width and --aspect give an image less than 2 pixels high");
+        process::exit(2);
+    }
+    if opts.indirect_clamp.is_some_and(|limit| limit <= 0.0) {
+        eprintln!("error: --indirect-clamp must be positive");
+        process::exit(2);
+    }
+    if opts.ortho.is_some_and(|height| height <= 0.0) {
+        eprintln!("error: --ortho must be positive");
+        process::exit(2);
+    }
+    if matches!(opts.tonemap, ToneMap::ReinhardExtended(white) if white <= 0.0) {
+        eprintln!("error: --white-point must be positive");
+        process::exit(2);
+    }
+    if opts.gamma <= 0.0 {
+        eprintln!("error: --gamma must be positive");
+        process::exit(2);
+    }
+    if opts.edge_aa.is_some() && (opts.stream || opts.orbit.is_some()) {
+        eprintln!("error: --edge-aa and --coverage-aa need the whole frame and cannot be combined with --stream or --orbit");
+        process::exit(2);
+    }
+    if opts.light_view.is_some() && (opts.camera.is_some() || opts.all_cameras) {
+        eprintln!("error: --light-view cannot be combined with --camera or --all-cameras");
+        process::exit(2);
+    }
+    if opts.all_cameras {
+        if opts.camera.is_some() {
+            eprintln!("error: --camera and --all-cameras are mutually exclusive");
+            process::exit(2);
+        }
+        if opts.outputs.len() > 1 {
+            eprintln!("error: --all-cameras takes one --output, suffixed with each camera's name");
+            process::exit(2);
+        }
+        if opts.orbit.is_some()
+            || opts.stream
+            || opts.edge_aa.is_some()
+            || opts.tolerance.is_some()
+            || opts.variance_out.is_some()
+            || opts.histogram
+            || opts.checkpoint.is_some()
+            || opts.resume.is_some()
+        {
+            eprintln!("error: --all-cameras renders whole frames to files and cannot be combined with --orbit, --stream, --edge-aa, --coverage-aa, --tolerance, --variance-out, --histogram, --checkpoint or --resume");
+            process::exit(2);
+        }
+    }
+    if opts.orbit == Some(0) {
+        eprintln!("error: --orbit must be at least 1");
+        process::exit(2);
+    }
+    if opts.orbit.is_some()
+        && (opts.outputs.len() > 1 || opts.outputs.iter().any(|path| !path.contains("{}")))
+    {
+        eprintln!("error: --orbit takes one --output with {{}} in place of the frame number");
+        process::exit(2);
+    }
+    if let Some(path) = opts
+        .outputs
+        .iter()
+        .find(|path| OutputFormat::from_path(path).is_none())
+    {
+        eprintln!(
+            "error: unsupported output {:?}, expected .ppm, .pam, .png, .pfm or .hdr",
+            path
+        );
+        process::exit(2);
+    }
+    if opts.stream
+        && (opts.orbit.is_some()
+            || opts.outputs.len() > 1
+            || opts.outputs.iter().any(|path| {
+                !matches!(
+                    OutputFormat::from_path(path),
+                    Some(OutputFormat::Ppm | OutputFormat::Pam)
+                )
+            }))
+    {
+        eprintln!(
+            "error: --stream writes a single frame to stdout or to one .ppm or .pam --output"
+        );
+        process::exit(2);
+    }
+    if opts.variance_out.is_some() && opts.orbit.is_some() {
+        eprintln!("error: --variance-out cannot be combined with --orbit");
+        process::exit(2);
+    }
+    if let Some(tolerance) = opts.tolerance {
+        if tolerance <= 0.0 {
+            eprintln!("error: --tolerance must be positive");
+            process::exit(2);
+        }
+        if opts.stream || opts.orbit.is_some() || opts.edge_aa.is_some() {
+            eprintln!("error: --tolerance cannot be combined with --stream, --orbit, --edge-aa or --coverage-aa");
+            process::exit(2);
+        }
+        if opts.checkpoint.is_some() || opts.resume.is_some() {
+            eprintln!("error: --tolerance cannot be combined with --checkpoint or --resume");
+            process::exit(2);
+        }
+    }
+    if opts.min_samples < 2 {
+        eprintln!("error: --min-samples must be at least 2, a variance needs two samples");
+        process::exit(2);
+    }
+    if opts.samples_out.is_some() && opts.tolerance.is_none() {
+        eprintln!("error: --samples-out needs --tolerance");
+        process::exit(2);
+    }
+    let checkpoint_path = opts.checkpoint.as_ref().or(opts.resume.as_ref());
+    if checkpoint_path.is_some() && (opts.stream || opts.orbit.is_some() || opts.edge_aa.is_some())
+    {
+        eprintln!("error: --checkpoint and --resume cannot be combined with --stream, --orbit, --edge-aa or --coverage-aa");
+        process::exit(2);
+    }
+    if opts.checkpoint_every == 0 {
+        eprintln!("error: --checkpoint-every must be positive");
+        process::exit(2);
+    }
+    let resumed = opts.resume.as_ref().map(|path| {
+        let checkpoint = Checkpoint::load(path).unwrap_or_else(|err| {
+            eprintln!("error: cannot load checkpoint {:?}: {}", path, err);
+            process::exit(2);
+        });
+        if (checkpoint.width, checkpoint.height) != (image_width, image_height) {
+            eprintln!(
+                "error: checkpoint {:?} is {}x{}, not {}x{}",
+                path, checkpoint.width, checkpoint.height, image_width, image_height
+            );
+            process::exit(2);
+        }
+        if checkpoint.seed != opts.seed {
+            eprintln!(
+                "error: checkpoint {:?} was rendered with a different --seed",
+                path
+            );
+            process::exit(2);
+        }
+        checkpoint
+    });
+    if opts.bvh_leaf_size == 0 {
+        eprintln!("error: --bvh-leaf-size must be at least 1");
+        process::exit(2);
+    }
+    if opts.shutter_close < opts.shutter_open {
+        eprintln!("error: --shutter-close must not be before --shutter-open");
+        process::exit(2);
+    }
+    // Debug modes trace a single path per pixel
+    let samples_per_pixel = match opts.mode {
+        Mode::Beauty => samples,
+        Mode::Bounces | Mode::Coc | Mode::Normals | Mode::Aabb => 1,
+    };
+    let mut histogram = opts.histogram.then(|| Histogram::new(samples_per_pixel));
+    if opts.material_stats {
+        material::enable_dielectric_stats();
+    }
+    if opts.stats {
+        stats::enable();
+    }
 
     // World
-    let world = random_scene();
+    let builder = WorldBuilder::new()
+        .cull_backfaces(opts.cull_backfaces)
+        .bvh_leaf_size(opts.bvh_leaf_size)
+        .split_glass(opts.split_glass);
+    let builder = match &opts.mesh {
+        Some(path) => {
+            let mat = Arc::new(Lambertian::new(Color::new(0.6, 0.6, 0.6)));
+            let triangles = mesh::load_obj(path, mat).unwrap_or_else(|err| {
+                eprintln!("error: cannot load mesh {:?}: {}", path, err);
+                process::exit(2);
+            });
+            triangles.into_iter().fold(builder, |builder, triangle| {
+                let rotated = RotateY::new(triangle, opts.mesh_rotate);
+                builder.object(Translate::new(rotated, opts.mesh_offset))
+            })
+        }
+        None => builder,
+    };
+    let world = match &scene_file {
+        Some(scene_file) => match &scene_file.background {
+            Some(background) => scene_file.build(builder.background(load_background(background))),
+            None => scene_file.build(builder),
+        },
+        None => builtin_scene(&opts, builder),
+    };
 
-    // Camera
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
+    if opts.energy_check {
+        check_energy(&world, &mut stream_rng(opts.seed, 0));
+    }
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.0,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
+    let max_depth = match opts.max_depth {
+        MaxDepth::Auto => {
+            let depth = auto_max_depth(&world);
+            let (specular, dielectric) = specular_shares(&world);
+            eprintln!(
+                "Max depth: {} (auto, {:.0}% specular, {:.0}% dielectric)",
+                depth,
+                100.0 * specular,
+                100.0 * dielectric
+            );
+            depth
+        }
+        MaxDepth::Fixed(depth) => depth,
+    };
+
+    // Camera
+    // The built-in scenes have the one default camera
+    let cameras = scene_file.as_ref().map_or_else(
+        || vec![("default".to_string(), CameraSettings::default())],
+        |scene_file| scene_file.cameras.clone(),
     );
+    let view = match opts.light_view {
+        Some(idx) => light_view(&world, idx),
+        None => scene::named_camera(&cameras, opts.camera.as_deref()),
+    }
+    .unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(2);
+    });
 
-    println!("P3");
-    println!("{} {}", IMAGE_WIGHT, IMAGE_HEIGHT);
-    println!("255");
+    // From `lookfrom` rather than the view's own, for the turntable
+    let new_camera = |view: &CameraSettings, lookfrom: Point3| {
+        let camera = CameraBuilder::new()
+            .lookfrom(lookfrom)
+            .lookat(view.lookat)
+            .vup(view.vup)
+            .aspect_ratio(aspect_ratio)
+            .shutter(opts.shutter_open, opts.shutter_close);
+        match opts.ortho {
+            Some(height) => camera.orthographic(height),
+            None => camera
+                .vfov(view.vfov)
+                .aperture(view.aperture)
+                .focus_dist(view.focus_dist),
+        }
+        .build()
+    };
 
-    for j in (0..IMAGE_HEIGHT).rev() {
-        eprintln!("Scanlines remaining: {}", j);
+    let settings = Settings {
+        max_depth,
+        seed: opts.seed,
+        sampling: opts.sampling,
+        normal_offset: opts.normal_offset,
+        vignette: opts.vignette,
+        mode: opts.mode,
+        normal_space: opts.normal_space,
+        alpha: opts.alpha,
+        matte_metals: opts.matte_metals,
+        russian_roulette: opts.russian_roulette,
+        no_light_sampling: opts.no_light_sampling,
+        indirect_clamp: opts.indirect_clamp,
+        background: opts.background.as_ref().map(load_background),
+        ground_color: opts.ground_color,
+        progress: true,
+        ..Settings::new(image_width, image_height, samples_per_pixel)
+    };
+    let options_hash = render_hash(&opts, &settings, &view);
+    if let (Some(checkpoint), Some(path)) = (&resumed, &opts.resume) {
+        if checkpoint.options != options_hash {
+            eprintln!(
+                "error: checkpoint {:?} was rendered with different render options",
+                path
+            );
+            process::exit(2);
+        }
+    }
+    let renderer = Renderer::new(&world, &settings);
+    let new_writer = |out: Box<dyn Write>, alpha: bool| {
+        ImageWriter::new(
+            BufWriter::new(out),
+            image_width,
+            image_height,
+            samples_per_pixel,
+            alpha,
+        )
+        .map(|writer| writer.with_tonemap(opts.tonemap, opts.gamma))
+    };
 
-        let scanline: Vec<Color> = (0..IMAGE_WIGHT)
-            .into_par_iter()
-            .map(|i| {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                let mut rng = thread_rng();
-                for _ in 0..SAMPLES_PER_PIXEL {
-                    let random_u: f64 = rng.gen();
-                    let random_v: f64 = rng.gen();
+    // One finished frame to a file, in the format its extension names
+    let write_file = |path: &str, pixels: &[Pixel]| -> io::Result<()> {
+        let write_image = |alpha: bool| -> io::Result<()> {
+            let mut writer = new_writer(Box::new(File::create(path)?), alpha)?;
+            for pixel in pixels {
+                writer.write_pixel(pixel.color, pixel.coverage)?;
+            }
+            writer.flush()
+        };
+        let colors = || pixels.iter().map(|pixel| pixel.color).collect::<Vec<_>>();
+        match OutputFormat::from_path(path) {
+            Some(OutputFormat::Ppm) => write_image(false),
+            Some(OutputFormat::Pam) => write_image(true),
+            Some(OutputFormat::Png) => output::write_png(
+                BufWriter::new(File::create(path)?),
+                image_width,
+                image_height,
+                samples_per_pixel,
+                opts.tonemap,
+                opts.gamma,
+                &colors(),
+            ),
+            Some(OutputFormat::Pfm) => output::write_pfm(
+                BufWriter::new(File::create(path)?),
+                image_width,
+                image_height,
+                samples_per_pixel,
+                &colors(),
+            ),
+            Some(OutputFormat::Hdr) => output::write_hdr(
+                BufWriter::new(File::create(path)?),
+                image_width,
+                image_height,
+                samples_per_pixel,
+                &colors(),
+            ),
+            None => unreachable!("output formats are checked before rendering"),
+        }
+    };
 
-                    let u = ((i as f64) + random_u) / ((IMAGE_WIGHT - 1) as f64);
-                    let v = ((j as f64) + random_v) / ((IMAGE_HEIGHT - 1) as f64);
+    // Rendering and writing the outputs, what --stats measures the ray throughput over
+    let render_start = Instant::now();
+    if let Some(frames) = opts.orbit {
+        // Turntable around the vertical axis through lookat, one file per frame as it is done
+        let pattern = match opts.outputs.first() {
+            Some(pattern) => pattern.clone(),
+            None if opts.alpha => "orbit_{}.pam".to_string(),
+            None => "orbit_{}.ppm".to_string(),
+        };
+        // A full turn leaves out the frame that would repeat the first, a partial sweep
+        // ends on its last angle
+        let steps = if opts.orbit_sweep.abs() >= 360.0 {
+            frames
+        } else {
+            (frames - 1).max(1)
+        };
+        for frame in 0..frames {
+            let angle = opts.orbit_sweep.to_radians() * frame as f64 / steps as f64;
+            let cam = new_camera(
+                &view,
+                view.lookat + (view.lookfrom - view.lookat).rotate_y(angle),
+            );
 
-                    let r = cam.get_ray(u, v);
-                    pixel_color += ray_color(&r, &world, MAX_DEPTH);
-                }
+            let path = pattern.replace("{}", &format!("{:04}", frame));
+            eprintln!("Frame {}/{}: {}", frame + 1, frames, path);
+            // Its own pass for every frame, so the noise does not stick to the screen
+            let pixels = renderer.render_frame(&cam, samples_per_pixel, frame);
+            write_file(&path, &pixels)?;
+            // One histogram per frame, under the frame's own line
+            if opts.histogram {
+                let mut histogram = Histogram::new(samples_per_pixel);
+                pixels.iter().for_each(|pixel| histogram.add(pixel.color));
+                histogram.print();
+            }
 
-                pixel_color
-            })
-            .collect();
+            if interrupt::requested() {
+                eprintln!("Interrupted, the last frame got a single sample per remaining pixel");
+                break;
+            }
+        }
+        if opts.stats {
+            stats::print(render_start.elapsed());
+        }
+        eprintln!("Done.");
+        return Ok(());
+    }
 
-        for pixel_color in scanline {
-            println!("{}", pixel_color.format_color(SAMPLES_PER_PIXEL));
+    if opts.all_cameras {
+        let path = match opts.outputs.first() {
+            Some(path) => path.clone(),
+            None if opts.alpha => "camera.pam".to_string(),
+            None => "camera.ppm".to_string(),
+        };
+        for (idx, (name, view)) in cameras.iter().enumerate() {
+            let path = suffixed(&path, name);
+            eprintln!("Camera {}/{}: {}", idx + 1, cameras.len(), path);
+            let cam = new_camera(view, view.lookfrom);
+            write_file(&path, &renderer.render_frame(&cam, samples_per_pixel, 0))?;
+
+            if interrupt::requested() {
+                eprintln!("Interrupted, the last image got a single sample per remaining pixel");
+                break;
+            }
+        }
+        if opts.stats {
+            stats::print(render_start.elapsed());
         }
-        eprint!("\x1b[1A\x1b[2K\r") // cursor up and clear line
+        eprintln!("Done.");
+        return Ok(());
+    }
+
+    let cam = new_camera(&view, view.lookfrom);
+
+    let mut pixels: Vec<Pixel> = Vec::new();
+    let mut variances = Vec::new();
+    let mut sample_counts = Vec::new();
+    if opts.stream {
+        let mut writer = match opts.outputs.first() {
+            Some(path) => new_writer(
+                Box::new(File::create(path)?),
+                matches!(OutputFormat::from_path(path), Some(OutputFormat::Pam)),
+            )?,
+            None => new_writer(Box::new(io::stdout()), opts.alpha)?,
+        };
+        renderer.render(&cam, |scanline| {
+            if let Some(histogram) = histogram.as_mut() {
+                scanline.iter().for_each(|pixel| histogram.add(pixel.color));
+            }
+            if opts.variance_out.is_some() {
+                variances.extend(
+                    scanline
+                        .iter()
+                        .map(|pixel| pixel.variance(samples_per_pixel)),
+                );
+            }
+            for pixel in scanline {
+                writer.write_pixel(pixel.color, pixel.coverage)?;
+            }
+            writer.flush()
+        })?;
+    } else {
+        pixels = match (opts.tolerance, checkpoint_path, opts.edge_aa) {
+            (Some(tolerance), _, _) => {
+                let (pixels, counts) = renderer.render_adaptive(&cam, tolerance, opts.min_samples);
+                let total: u64 = counts.iter().sum();
+                let full = samples_per_pixel * counts.len() as u64;
+                eprintln!(
+                    "Adaptive: {:.1} samples per pixel on average, {:.1}% of {}",
+                    total as f64 / counts.len() as f64,
+                    100.0 * total as f64 / full as f64,
+                    samples_per_pixel
+                );
+                sample_counts = counts;
+                pixels
+            }
+            (None, Some(path), _) => {
+                let checkpoint = resumed.unwrap_or_else(|| {
+                    Checkpoint::new(
+                        image_width,
+                        image_height,
+                        opts.seed,
+                        options_hash,
+                        opts.checkpoint_every,
+                    )
+                });
+                renderer.render_checkpointed(&cam, checkpoint, path)?
+            }
+            (None, None, Some(detect)) => {
+                renderer.render_edge_aa(&cam, (samples_per_pixel / 8).max(1), detect)
+            }
+            (None, None, None) => render::render(&world, &cam, &settings).pixels,
+        };
+        if opts.variance_out.is_some() {
+            variances = pixels
+                .iter()
+                .map(|pixel| pixel.variance(samples_per_pixel))
+                .collect();
+        }
+        if let Some(histogram) = histogram.as_mut() {
+            pixels.iter().for_each(|pixel| histogram.add(pixel.color));
+        }
+    }
+
+    // Checkpointed renders say themselves what they kept
+    if interrupt::requested() && checkpoint_path.is_none() {
+        eprintln!("Interrupted, the remaining pixels got a single sample");
+    }
+
+    // All outputs are formatted from the same accumulated sums
+    if opts.stream {
+        // Already written row by row
+        if let Some(path) = opts.outputs.first() {
+            eprintln!("Wrote {}", path);
+        }
+    } else {
+        if opts.outputs.is_empty() {
+            let mut writer = new_writer(Box::new(io::stdout()), opts.alpha)?;
+            for pixel in &pixels {
+                writer.write_pixel(pixel.color, pixel.coverage)?;
+            }
+            writer.flush()?;
+        }
+        for path in &opts.outputs {
+            write_file(path, &pixels)?;
+            eprintln!("Wrote {}", path);
+        }
+    }
+
+    if let Some(path) = &opts.variance_out {
+        // Through the same gamma 2 as the beauty image, grey levels read as standard deviation
+        let mut writer = ImageWriter::new(
+            BufWriter::new(File::create(path)?),
+            image_width,
+            image_height,
+            1,
+            false,
+        )?;
+        for variance in variances {
+            writer.write_pixel(Color::new(variance, variance, variance), 1.0)?;
+        }
+        writer.flush()?;
+    }
+
+    if let Some(path) = &opts.samples_out {
+        let mut writer = ImageWriter::new(
+            BufWriter::new(File::create(path)?),
+            image_width,
+            image_height,
+            1,
+            false,
+        )?;
+        for count in sample_counts {
+            // Squared, so the output gamma leaves the heatmap colors as they are
+            let color = heatmap(count as f64 / samples_per_pixel as f64);
+            writer.write_pixel(color * color, 1.0)?;
+        }
+        writer.flush()?;
+    }
+
+    if let Some(histogram) = histogram {
+        histogram.print();
+    }
+    if opts.stats {
+        stats::print(render_start.elapsed());
+    }
+    if opts.material_stats {
+        let stats = material::dielectric_stats();
+        let reflected = stats.total_internal + stats.reflected;
+        let total = (reflected + stats.refracted).max(1);
+        eprintln!(
+            "Dielectric: reflected {} ({:.1}%, of which {} total internal), refracted {} ({:.1}%)",
+            reflected,
+            100.0 * reflected as f64 / total as f64,
+            stats.total_internal,
+            stats.refracted,
+            100.0 * stats.refracted as f64 / total as f64
+        );
+    }
+    eprintln!("Done.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<Options, String> {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let env = env
+            .iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from(value)));
+        Options::parse(&Args::new(args, env))
+    }
+
+    fn options(args: &[&str], env: &[(&str, &str)]) -> Options {
+        parse(args, env).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[test]
+    fn environment_fills_in_what_the_command_line_leaves_out() {
+        let env = [
+            ("RAYRUST_WIDTH", "200"),
+            ("RAYRUST_SAMPLES", "7"),
+            ("RAYRUST_SEED", "3"),
+            ("RAYRUST_STATS", "1"),
+            ("RAYRUST_ALPHA", "0"),
+        ];
+        let opts = options(&[], &env);
+        assert_eq!(opts.width, Some(200));
+        assert_eq!(opts.samples, Some(7));
+        assert_eq!(opts.seed, Some(3));
+        assert!(opts.stats && !opts.alpha);
+
+        // Defaults for what neither gives
+        let opts = options(&[], &[]);
+        assert_eq!((opts.width, opts.samples, opts.seed), (None, None, None));
+        assert_eq!(opts.min_samples, 16);
+    }
+
+    #[test]
+    fn command_line_beats_the_environment() {
+        let env = [("RAYRUST_WIDTH", "200"), ("RAYRUST_SAMPLES", "7")];
+        let opts = options(&["--width", "320", "--seed", "5"], &env);
+        assert_eq!(opts.width, Some(320));
+        assert_eq!(opts.samples, Some(7));
+        assert_eq!(opts.seed, Some(5));
+
+        // Even over a bad value, which is then never read
+        let opts = options(&["--width", "320"], &[("RAYRUST_WIDTH", "wide")]);
+        assert_eq!(opts.width, Some(320));
+    }
+
+    #[test]
+    fn bad_values_and_unknown_options_are_reported() {
+        let err = parse(&[], &[("RAYRUST_SAMPLES", "many")]).err().unwrap();
+        assert_eq!(err, "RAYRUST_SAMPLES expects a valid value");
+        let err = parse(&["--samples", "-3"], &[]).err().unwrap();
+        assert_eq!(err, "--samples expects a valid value");
+        let err = parse(&["--width"], &[]).err().unwrap();
+        assert_eq!(err, "--width expects a valid value");
+
+        let err = parse(&["--widht", "300"], &[]).err().unwrap();
+        assert_eq!(err, "unknown option --widht");
+        let err = parse(&["--width", "300", "300"], &[]).err().unwrap();
+        assert_eq!(err, "unexpected argument \"300\"");
+        let err = parse(&["--edge-aa"], &[("RAYRUST_COVERAGE_AA", "1")])
+            .err()
+            .unwrap();
+        assert!(err.contains("mutually exclusive"));
     }
-    eprintln!("Done.")
 }
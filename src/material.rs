@@ -1,58 +1,464 @@
 use crate::{
     hit::HitRecord,
     ray::Ray,
-    vec::{Color, Vec3},
+    texture::{SolidColor, Texture},
+    vec::{Color, Point3, Vec3},
 };
-use rand::{thread_rng, Rng};
+use rand::{Rng, RngCore};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum MaterialKind {
+    Diffuse,
+    Specular,
+    Dielectric,
+    Emissive,
+}
+
+// A surface material. Only `scatter` is required, the rest default to a surface that emits
+// nothing, gets no direct light sampling and is picked at random like any diffuse one.
 pub trait Scatter: Send + Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    // Attenuation and outgoing ray for a ray arriving at `rec`, None if the ray is absorbed.
+    // Randomness must come from `rng` alone, which keeps --seed renders reproducible.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)>;
+
+    // Albedo to shade with when specular reflection is disabled for a matte preview
+    fn matte_albedo(&self) -> Option<Color> {
+        None
+    }
+
+    // Albedo of an ideal diffuse surface at the hit, which gets direct light sampling
+    fn diffuse_albedo(&self, _rec: &HitRecord) -> Option<Color> {
+        None
+    }
+
+    fn emitted(&self) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Every outgoing ray with its weight, for materials that can be traced without
+    // a random choice. The renderer may still fall back to `scatter`.
+    fn split(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<Vec<(Color, Ray)>> {
+        None
+    }
+
+    fn kind(&self) -> MaterialKind {
+        MaterialKind::Diffuse
+    }
+
+    // Normal to shade the hit with, which a material may bend away from the surface's own.
+    // The renderer applies it right after the hit, so scattering and light sampling agree.
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.normal
+    }
+
+    // Seen through rather than shaded, see ShadowCatcher
+    fn catches_shadows(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
 
 impl Scatter for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
-        if scatter_direction.near_zero() {
-            // Catch degenerate scatter direction
-            scatter_direction = rec.normal;
-        }
-        let scattered = Ray::new(rec.p, scatter_direction);
-        Some((self.albedo, scattered))
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let scattered = diffuse_bounce(r_in, rec, rng);
+        Some((self.albedo.value(rec.u, rec.v, &rec.p), scattered))
+    }
+
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        Some(self.albedo.value(rec.u, rec.v, &rec.p))
     }
 }
 
+// Lambertian's scattered ray, for surfaces rendered matte without building one
+pub fn diffuse_bounce(r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Ray {
+    // A point uniform on the unit sphere tangent to the surface at the normal's tip is
+    // exactly cosine distributed around the normal, as seen from the hit point, so this
+    // already samples the Lambertian lobe with density cos(theta) / pi
+    let mut scatter_direction = rec.normal + Vec3::random_unit_vector(rng);
+    if scatter_direction.near_zero() {
+        // Catch degenerate scatter direction
+        scatter_direction = rec.normal;
+    }
+    Ray::new(rec.p, scatter_direction, r_in.time())
+}
+
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    // Treat albedo as F0 and brighten towards white at grazing angles
+    fresnel: bool,
+    // Brushed finish, see with_brush
+    brush: Option<Brush>,
+}
+
+struct Brush {
+    tangent: Vec3,
+    tangent_fuzz: f64,
+    bitangent_fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Self { albedo, fuzz }
+        Self {
+            albedo,
+            fuzz,
+            fresnel: false,
+            brush: None,
+        }
+    }
+
+    pub fn with_fresnel(self, fresnel: bool) -> Self {
+        Self { fresnel, ..self }
+    }
+
+    // Stretch the fuzz into a streak: `tangent_fuzz` along `tangent`, as projected onto the
+    // surface, and `bitangent_fuzz` across it. Equal values are plain Metal with that fuzz.
+    pub fn with_brush(self, tangent: Vec3, tangent_fuzz: f64, bitangent_fuzz: f64) -> Self {
+        if tangent_fuzz == bitangent_fuzz {
+            return Self {
+                fuzz: tangent_fuzz,
+                brush: None,
+                ..self
+            };
+        }
+        Self {
+            // Off the surface, where the tangent runs along the normal, it is isotropic
+            fuzz: 0.5 * (tangent_fuzz + bitangent_fuzz),
+            brush: Some(Brush {
+                tangent,
+                tangent_fuzz,
+                bitangent_fuzz,
+            }),
+            ..self
+        }
+    }
+
+    // Random offset added to the mirror direction
+    fn fuzz_offset(&self, normal: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let offset = Vec3::random_in_unit_sphere(rng);
+        let brush = match &self.brush {
+            Some(brush) => brush,
+            None => return self.fuzz * offset,
+        };
+        let tangent = brush.tangent - brush.tangent.dot(normal) * normal;
+        if tangent.near_zero() {
+            return self.fuzz * offset;
+        }
+        // Scaled per axis of the frame around the normal, the normal's own axis by the mean
+        let tangent = tangent.normalized();
+        let bitangent = normal.cross(tangent);
+        brush.tangent_fuzz * offset.dot(tangent) * tangent
+            + brush.bitangent_fuzz * offset.dot(bitangent) * bitangent
+            + self.fuzz * offset.dot(normal) * normal
     }
 }
 
 impl Scatter for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let reflected = r_in.direction().normalized().reflect(rec.normal);
-        let scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let unit_direction = r_in.direction().normalized();
+        let reflected = unit_direction.reflect(rec.normal);
+        let scattered = Ray::new(
+            rec.p,
+            reflected + self.fuzz_offset(rec.normal, rng),
+            r_in.time(),
+        );
 
+        let attenuation = if self.fresnel {
+            let cos_theta = (-unit_direction).dot(rec.normal).clamp(0.0, 1.0);
+            let f = |f0: f64| schlick(cos_theta, f0);
+            Color::new(f(self.albedo[0]), f(self.albedo[1]), f(self.albedo[2]))
+        } else {
+            self.albedo
+        };
         if scattered.direction().dot(rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
+            Some((attenuation, scattered))
         } else {
             None
         }
     }
+
+    fn matte_albedo(&self) -> Option<Color> {
+        Some(self.albedo)
+    }
+
+    fn kind(&self) -> MaterialKind {
+        MaterialKind::Specular
+    }
+}
+
+pub struct Mirror {
+    albedo: Color,
+}
+
+impl Mirror {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Scatter for Mirror {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, _rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        // Exact reflection, no fuzz sampling
+        let reflected = r_in.direction().normalized().reflect(rec.normal);
+        Some((self.albedo, Ray::new(rec.p, reflected, r_in.time())))
+    }
+
+    fn matte_albedo(&self) -> Option<Color> {
+        Some(self.albedo)
+    }
+
+    fn kind(&self) -> MaterialKind {
+        MaterialKind::Specular
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self) -> Color {
+        self.emit
+    }
+
+    fn kind(&self) -> MaterialKind {
+        MaterialKind::Emissive
+    }
+}
+
+// Ground for compositing onto a photo. Rays pass through it, darkened by the share of the
+// light that the rest of the scene blocks from the hit, so only shadows are left of it.
+// With an alpha channel it shows black with the shadow as its alpha instead.
+pub struct ShadowCatcher;
+
+impl Scatter for ShadowCatcher {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn catches_shadows(&self) -> bool {
+        true
+    }
+}
+
+// Phase function of a participating medium, scatters evenly into every direction
+pub struct Isotropic {
+    albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Scatter for Isotropic {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let scattered = Ray::new(rec.p, Vec3::random_unit_vector(rng), r_in.time());
+        Some((self.albedo, scattered))
+    }
+}
+
+// Another material with its normal bent by a tangent-space normal map. A map color c stands for
+// the vector 2c - 1 in a frame of tangent, bitangent and normal, so (0.5, 0.5, 1) is no change.
+// The tangent runs around the y axis, the direction u grows in on a sphere, and the bitangent
+// up towards +y like v. Other shapes get the same frame, whatever their own (u, v).
+pub struct NormalMapped {
+    inner: Arc<dyn Scatter>,
+    map: Arc<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new(inner: Arc<dyn Scatter>, map: Arc<dyn Texture>) -> Self {
+        Self { inner, map }
+    }
+}
+
+impl Scatter for NormalMapped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        self.inner.scatter(r_in, rec, rng)
+    }
+
+    fn matte_albedo(&self) -> Option<Color> {
+        self.inner.matte_albedo()
+    }
+
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        self.inner.diffuse_albedo(rec)
+    }
+
+    fn emitted(&self) -> Color {
+        self.inner.emitted()
+    }
+
+    fn split(&self, r_in: &Ray, rec: &HitRecord) -> Option<Vec<(Color, Ray)>> {
+        self.inner.split(r_in, rec)
+    }
+
+    fn kind(&self) -> MaterialKind {
+        self.inner.kind()
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let color = self.map.value(rec.u, rec.v, &rec.p);
+        let m = 2.0 * color - Vec3::new(1.0, 1.0, 1.0);
+        // Out of gamut colors and normals lying in or under the surface leave it as it is
+        if color.iter().any(|c| !(0.0..=1.0).contains(c)) || m.z() <= 0.0 {
+            return rec.normal;
+        }
+        let outward = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        let tangent = Vec3::new(0.0, 1.0, 0.0).cross(outward);
+        if tangent.near_zero() {
+            // At the poles the frame is undefined
+            return rec.normal;
+        }
+        let tangent = tangent.normalized();
+        let bitangent = outward.cross(tangent);
+        let bent = (m.x() * tangent + m.y() * bitangent + m.z() * outward).normalized();
+        if rec.front_face {
+            bent
+        } else {
+            -bent
+        }
+    }
+}
+
+// Schlick's approximation of Fresnel reflectance, from `f0` at normal incidence up to 1
+fn schlick(cosine: f64, f0: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cosine).powi(5)
+}
+
+// Mean attenuation of white light arriving from random directions above a flat surface.
+// Absorbed rays count as black, so anything over 1.0 in a channel means energy gain.
+pub fn material_albedo_estimate(mat: &dyn Scatter, rng: &mut dyn RngCore, samples: u64) -> Color {
+    let normal = Vec3::new(0.0, 1.0, 0.0);
+    let mut rec = HitRecord {
+        p: Point3::new(0.0, 0.0, 0.0),
+        normal,
+        // Materials only read the geometry of the record
+        mat: Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))),
+        t: 1.0,
+        u: 0.0,
+        v: 0.0,
+        front_face: true,
+    };
+
+    let mut total = Color::new(0.0, 0.0, 0.0);
+    for _ in 0..samples {
+        let direction = -Vec3::random_in_hemisphere(normal, rng);
+        let r_in = Ray::new(rec.p - direction, direction, 0.0);
+        rec.set_face_normal(&r_in, normal);
+        if let Some((attenuation, _)) = mat.scatter(&r_in, &rec, rng) {
+            total += attenuation;
+        }
+    }
+    total / samples.max(1) as f64
+}
+
+// Polished metals by linear albedo (F0 at normal incidence)
+const METAL_PRESETS: &[(&str, [f64; 3])] = &[
+    ("gold", [1.0, 0.71, 0.29]),
+    ("silver", [0.95, 0.93, 0.88]),
+    ("copper", [0.95, 0.64, 0.54]),
+    ("aluminium", [0.91, 0.92, 0.92]),
+    ("iron", [0.56, 0.57, 0.58]),
+];
+
+// Clear dielectrics by index of refraction
+const DIELECTRIC_PRESETS: &[(&str, f64)] = &[
+    ("water", 1.333),
+    ("glass", 1.5),
+    ("bk7", 1.5168),
+    ("sapphire", 1.77),
+    ("diamond", 2.42),
+];
+
+pub fn preset_names() -> impl Iterator<Item = &'static str> {
+    let metals = METAL_PRESETS.iter().map(|(name, _)| *name);
+    metals.chain(DIELECTRIC_PRESETS.iter().map(|(name, _)| *name))
+}
+
+pub fn preset(name: &str) -> Result<Arc<dyn Scatter>, String> {
+    if let Some((_, [r, g, b])) = METAL_PRESETS.iter().find(|(preset, _)| *preset == name) {
+        let metal = Metal::new(Color::new(*r, *g, *b), 0.0).with_fresnel(true);
+        return Ok(Arc::new(metal));
+    }
+    if let Some((_, ir)) = DIELECTRIC_PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+    {
+        return Ok(Arc::new(Dielectric::new(*ir)));
+    }
+    Err(format!(
+        "unknown material preset {:?}, expected one of {}",
+        name,
+        preset_names().collect::<Vec<_>>().join(", ")
+    ))
+}
+
+// Reflect/refract tallies across all Dielectric instances, off unless enabled
+static DIELECTRIC_STATS: AtomicBool = AtomicBool::new(false);
+static DIELECTRIC_TIR: AtomicU64 = AtomicU64::new(0);
+static DIELECTRIC_REFLECTED: AtomicU64 = AtomicU64::new(0);
+static DIELECTRIC_REFRACTED: AtomicU64 = AtomicU64::new(0);
+
+pub struct DielectricStats {
+    // Total internal reflection, refraction was impossible
+    pub total_internal: u64,
+    // Reflection chosen by the Schlick term
+    pub reflected: u64,
+    pub refracted: u64,
+}
+
+pub fn enable_dielectric_stats() {
+    DIELECTRIC_STATS.store(true, Ordering::Relaxed);
+}
+
+pub fn dielectric_stats() -> DielectricStats {
+    DielectricStats {
+        total_internal: DIELECTRIC_TIR.load(Ordering::Relaxed),
+        reflected: DIELECTRIC_REFLECTED.load(Ordering::Relaxed),
+        refracted: DIELECTRIC_REFRACTED.load(Ordering::Relaxed),
+    }
 }
 
 pub struct Dielectric {
@@ -66,20 +472,23 @@ impl Dielectric {
         }
     }
 
+    fn refraction_ratio(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            1.0 / self.ir
+        } else {
+            self.ir
+        }
+    }
+
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        // Use Schlick's approximation for reflectance
         let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+        schlick(cosine, r0)
     }
 }
 
 impl Scatter for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        let refraction_ratio = self.refraction_ratio(rec);
 
         let unit_direction = r_in.direction().normalized();
         let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
@@ -87,17 +496,395 @@ impl Scatter for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let random = thread_rng().gen::<f64>();
+        let random = rng.gen::<f64>();
         let will_reflect = Self::reflectance(cos_theta, refraction_ratio) > random;
 
+        if DIELECTRIC_STATS.load(Ordering::Relaxed) {
+            let counter = if cannot_refract {
+                &DIELECTRIC_TIR
+            } else if will_reflect {
+                &DIELECTRIC_REFLECTED
+            } else {
+                &DIELECTRIC_REFRACTED
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
         let direction = if cannot_refract || will_reflect {
             unit_direction.reflect(rec.normal)
         } else {
             unit_direction.refract(rec.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(rec.p, direction);
+        let scattered = Ray::new(rec.p, direction, r_in.time());
 
         Some((Color::new(1.0, 1.0, 1.0), scattered))
     }
+    fn kind(&self) -> MaterialKind {
+        MaterialKind::Dielectric
+    }
+}
+
+// Glass that follows both the reflected and the refracted ray, weighted by Fresnel,
+// instead of picking one. Smooth at low sample counts at the cost of more rays.
+pub struct SplitDielectric {
+    glass: Dielectric,
+}
+
+impl SplitDielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            glass: Dielectric::new(index_of_refraction),
+        }
+    }
+}
+
+impl Scatter for SplitDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<(Color, Ray)> {
+        self.glass.scatter(r_in, rec, rng)
+    }
+
+    fn split(&self, r_in: &Ray, rec: &HitRecord) -> Option<Vec<(Color, Ray)>> {
+        let refraction_ratio = self.glass.refraction_ratio(rec);
+
+        let unit_direction = r_in.direction().normalized();
+        let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+
+        let reflected = Ray::new(rec.p, unit_direction.reflect(rec.normal), r_in.time());
+        if refraction_ratio * sin_theta > 1.0 {
+            return Some(vec![(Color::new(1.0, 1.0, 1.0), reflected)]);
+        }
+
+        let reflectance = Dielectric::reflectance(cos_theta, refraction_ratio);
+        let refracted = Ray::new(
+            rec.p,
+            unit_direction.refract(rec.normal, refraction_ratio),
+            r_in.time(),
+        );
+        Some(vec![
+            (Color::new(1.0, 1.0, 1.0) * reflectance, reflected),
+            (Color::new(1.0, 1.0, 1.0) * (1.0 - reflectance), refracted),
+        ])
+    }
+
+    fn kind(&self) -> MaterialKind {
+        MaterialKind::Dielectric
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    // A ray arriving at the origin along `direction`, and its hit on a surface facing `normal`
+    fn arriving(direction: Vec3, normal: Vec3) -> (Ray, HitRecord) {
+        let r_in = Ray::new(Point3::new(0.0, 0.0, 0.0) - direction, direction, 0.0);
+        let mut rec = HitRecord {
+            p: Point3::new(0.0, 0.0, 0.0),
+            normal,
+            mat: Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        };
+        rec.set_face_normal(&r_in, normal);
+        (r_in, rec)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1.0e-12, "{} != {}", a, b);
+    }
+
+    fn up() -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    #[test]
+    fn lambertian_scatters_above_the_surface() {
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let (r_in, rec) = arriving(Vec3::new(1.0, -1.0, 0.0), up());
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let (attenuation, scattered) = Lambertian::new(albedo)
+                .scatter(&r_in, &rec, &mut rng)
+                .unwrap();
+            assert_close(attenuation, albedo);
+            assert!(scattered.direction().dot(up()) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn lambertian_scatter_is_cosine_distributed() {
+        let normal = Vec3::new(1.0, 2.0, -0.5).normalized();
+        let (r_in, rec) = arriving(-normal, normal);
+        let lambertian = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut rng = StdRng::seed_from_u64(9);
+
+        // Density cos(theta) / pi puts b^2 - a^2 of the draws at cos(theta) in a..b
+        let draws = 200_000;
+        let mut buckets = [0u32; 10];
+        for _ in 0..draws {
+            let (_, scattered) = lambertian.scatter(&r_in, &rec, &mut rng).unwrap();
+            let cos_theta = scattered.direction().normalized().dot(normal);
+            assert!(cos_theta >= 0.0);
+            buckets[((cos_theta * 10.0) as usize).min(9)] += 1;
+        }
+        for (i, &count) in buckets.iter().enumerate() {
+            let (a, b) = (i as f64 / 10.0, (i + 1) as f64 / 10.0);
+            let share = count as f64 / draws as f64;
+            assert!(
+                (share - (b * b - a * a)).abs() < 0.003,
+                "cos(theta) in {}..{}: {} of the draws, expected {}",
+                a,
+                b,
+                share,
+                b * b - a * a
+            );
+        }
+    }
+
+    #[test]
+    fn metal_without_fuzz_reflects_and_fuzz_can_absorb() {
+        let (r_in, rec) = arriving(Vec3::new(1.0, -1.0, 0.0), up());
+        let mut rng = StdRng::seed_from_u64(2);
+        let (_, scattered) = Metal::new(Color::new(0.9, 0.9, 0.9), 0.0)
+            .scatter(&r_in, &rec, &mut rng)
+            .unwrap();
+        assert_close(scattered.direction(), Vec3::new(1.0, 1.0, 0.0).normalized());
+
+        // Grazing, a full unit of fuzz pushes some reflections below the surface
+        let (r_in, rec) = arriving(Vec3::new(1.0, -0.05, 0.0), up());
+        let metal = Metal::new(Color::new(0.9, 0.9, 0.9), 1.0);
+        let absorbed = (0..1000)
+            .filter(|_| metal.scatter(&r_in, &rec, &mut rng).is_none())
+            .count();
+        assert!(absorbed > 0 && absorbed < 1000);
+    }
+
+    #[test]
+    fn fresnel_metal_brightens_towards_grazing() {
+        let gold = Color::new(1.0, 0.71, 0.29);
+        let metal = Metal::new(gold, 0.0).with_fresnel(true);
+        let mut rng = StdRng::seed_from_u64(5);
+        let attenuation = |direction: Vec3, rng: &mut StdRng| {
+            let (r_in, rec) = arriving(direction, up());
+            metal.scatter(&r_in, &rec, rng).unwrap().0
+        };
+
+        // Head on it is the base color, F0
+        assert_close(attenuation(Vec3::new(0.0, -1.0, 0.0), &mut rng), gold);
+        // Rising with the angle of incidence, and close to white at grazing
+        let mut last = gold;
+        for slope in [1.0, 0.5, 0.2, 0.05, 0.001] {
+            let current = attenuation(Vec3::new(1.0, -slope, 0.0), &mut rng);
+            assert!(current[1] > last[1] && current[2] > last[2]);
+            assert!(current.max_component() <= 1.0);
+            last = current;
+        }
+        assert!((last - Color::new(1.0, 1.0, 1.0)).length() < 1e-2);
+
+        // Without the flag the albedo is used at every angle
+        let plain = Metal::new(gold, 0.0);
+        let (r_in, rec) = arriving(Vec3::new(1.0, -0.001, 0.0), up());
+        assert_close(plain.scatter(&r_in, &rec, &mut rng).unwrap().0, gold);
+    }
+
+    #[test]
+    fn brushed_metal_streaks_along_the_tangent() {
+        let albedo = Color::new(0.9, 0.9, 0.9);
+        let (r_in, rec) = arriving(Vec3::new(0.0, -1.0, 0.0), up());
+        // Mean squared spread of the unit scattered directions along x and z
+        let spread = |metal: &Metal, seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (mut x, mut z) = (0.0, 0.0);
+            for _ in 0..2000 {
+                let (_, scattered) = metal.scatter(&r_in, &rec, &mut rng).unwrap();
+                let direction = scattered.direction().normalized();
+                x += direction.x().powi(2);
+                z += direction.z().powi(2);
+            }
+            (x / 2000.0, z / 2000.0)
+        };
+
+        // The tangent is projected onto the surface first
+        let tangent = Vec3::new(1.0, 0.5, 0.0);
+        let brushed = Metal::new(albedo, 0.0).with_brush(tangent, 0.6, 0.1);
+        let (along, across) = spread(&brushed, 6);
+        assert!(along > 10.0 * across, "{} vs {}", along, across);
+        let turned = Metal::new(albedo, 0.0).with_brush(tangent, 0.1, 0.6);
+        let (along, across) = spread(&turned, 6);
+        assert!(across > 10.0 * along, "{} vs {}", along, across);
+
+        // Equal fuzz is plain Metal, ray for ray
+        let even = Metal::new(albedo, 0.0).with_brush(tangent, 0.3, 0.3);
+        let plain = Metal::new(albedo, 0.3);
+        let (mut rng_even, mut rng_plain) = (StdRng::seed_from_u64(7), StdRng::seed_from_u64(7));
+        for _ in 0..100 {
+            let a = even
+                .scatter(&r_in, &rec, &mut rng_even)
+                .map(|(_, r)| r.direction());
+            let b = plain
+                .scatter(&r_in, &rec, &mut rng_plain)
+                .map(|(_, r)| r.direction());
+            match (a, b) {
+                (Some(a), Some(b)) => assert_close(a, b),
+                (a, b) => assert_eq!(a.is_some(), b.is_some()),
+            }
+        }
+        let (x, z) = spread(&even, 6);
+        assert!((x - z).abs() < 0.2 * (x + z));
+    }
+
+    #[test]
+    fn normal_map_bends_the_normal_in_the_tangent_frame() {
+        let mapped = |color: Color| {
+            let inner = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+            NormalMapped::new(inner, Arc::new(SolidColor::new(color)))
+        };
+        let side = Vec3::new(1.0, 0.0, 0.0);
+        let (_, front) = arriving(Vec3::new(-1.0, 0.0, 0.0), side);
+        let (_, back) = arriving(Vec3::new(1.0, 0.0, 0.0), side);
+        assert!(front.front_face && !back.front_face);
+
+        // Flat, the normal is left as it is on either side
+        let flat = mapped(Color::new(0.5, 0.5, 1.0));
+        assert_close(flat.shading_normal(&front), side);
+        assert_close(flat.shading_normal(&back), -side);
+
+        // (0.6, 0, 0.8) in tangent space, where the tangent on the +x side runs along -z
+        let tilted = mapped(Color::new(0.8, 0.5, 0.9));
+        let bent = tilted.shading_normal(&front);
+        assert_close(bent, Vec3::new(0.8, 0.0, -0.6));
+        assert!((bent.length() - 1.0).abs() < 1e-12);
+        assert_close(tilted.shading_normal(&back), -bent);
+
+        // Bitangent up along +y, and unit length for a map that is not normalized
+        let up_and_out = mapped(Color::new(0.5, 1.0, 1.0)).shading_normal(&front);
+        assert_close(up_and_out, Vec3::new(1.0, 1.0, 0.0).normalized());
+
+        // Out of gamut or pointing into the surface, the map is ignored
+        assert_close(
+            mapped(Color::new(0.5, 0.5, 1.5)).shading_normal(&front),
+            side,
+        );
+        assert_close(
+            mapped(Color::new(0.8, 0.5, 0.4)).shading_normal(&front),
+            side,
+        );
+    }
+
+    #[test]
+    fn mirror_reflects_exactly_whatever_the_rng() {
+        let albedo = Color::new(0.9, 0.8, 0.7);
+        let (r_in, rec) = arriving(Vec3::new(2.0, -1.0, 0.5), up());
+        for seed in 0..4 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (attenuation, scattered) =
+                Mirror::new(albedo).scatter(&r_in, &rec, &mut rng).unwrap();
+            assert_close(attenuation, albedo);
+            assert_close(scattered.origin(), rec.p);
+            assert_close(scattered.direction(), Vec3::new(2.0, 1.0, 0.5).normalized());
+        }
+    }
+
+    #[test]
+    fn dielectric_reflects_past_the_critical_angle() {
+        // From inside glass at 60 degrees, beyond asin(1 / 1.5), whatever the random draw
+        let (r_in, rec) = arriving(Vec3::new(3.0f64.sqrt(), 1.0, 0.0), up());
+        assert!(!rec.front_face);
+        for rng in &mut [StepRng::new(0, 0), StepRng::new(u64::MAX, 0)] {
+            let (attenuation, scattered) = Dielectric::new(1.5).scatter(&r_in, &rec, rng).unwrap();
+            assert_close(attenuation, Color::new(1.0, 1.0, 1.0));
+            assert_close(
+                scattered.direction(),
+                Vec3::new(3.0f64.sqrt(), -1.0, 0.0).normalized(),
+            );
+        }
+    }
+
+    #[test]
+    fn dielectric_picks_reflection_by_the_random_draw() {
+        let (r_in, rec) = arriving(Vec3::new(1.0, -1.0, 0.0), up());
+        let glass = Dielectric::new(1.5);
+        // A draw of 0 is below any reflectance, a draw just under 1 above it
+        let (_, reflected) = glass.scatter(&r_in, &rec, &mut StepRng::new(0, 0)).unwrap();
+        assert_close(reflected.direction(), Vec3::new(1.0, 1.0, 0.0).normalized());
+        let (_, refracted) = glass
+            .scatter(&r_in, &rec, &mut StepRng::new(u64::MAX, 0))
+            .unwrap();
+        assert!(refracted.direction().y() < 0.0);
+        assert!((refracted.direction().x() - 0.5f64.sqrt() / 1.5).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn split_dielectric_weights_add_up_to_one() {
+        let (r_in, rec) = arriving(Vec3::new(1.0, -0.3, 0.0), up());
+        let children = SplitDielectric::new(1.5).split(&r_in, &rec).unwrap();
+        assert_eq!(children.len(), 2);
+        let total = children
+            .iter()
+            .fold(Color::new(0.0, 0.0, 0.0), |sum, (weight, _)| sum + *weight);
+        assert_close(total, Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn presets_look_up_by_name() {
+        // Diamond refracts like any dielectric of index 2.42
+        let (r_in, rec) = arriving(Vec3::new(1.0, -1.0, 0.0), up());
+        let diamond = preset("diamond").unwrap();
+        assert!(diamond.kind() == MaterialKind::Dielectric);
+        let (_, refracted) = diamond
+            .scatter(&r_in, &rec, &mut StepRng::new(u64::MAX, 0))
+            .unwrap();
+        assert!((refracted.direction().x() - 0.5f64.sqrt() / 2.42).abs() < 1.0e-12);
+
+        assert!(preset("gold").unwrap().kind() == MaterialKind::Specular);
+        let err = preset("unobtainium").err().unwrap();
+        assert!(err.contains("unobtainium") && err.contains("diamond"));
+    }
+
+    #[test]
+    fn diffuse_light_emits_and_absorbs() {
+        let (r_in, rec) = arriving(Vec3::new(0.0, -1.0, 0.0), up());
+        let light = DiffuseLight::new(Color::new(4.0, 4.0, 4.0));
+        assert!(light
+            .scatter(&r_in, &rec, &mut StdRng::seed_from_u64(3))
+            .is_none());
+        assert_close(light.emitted(), Color::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn materials_do_not_gain_energy() {
+        let materials: Vec<(&str, Arc<dyn Scatter>)> = vec![
+            (
+                "lambertian",
+                Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))),
+            ),
+            (
+                "metal",
+                Arc::new(Metal::new(Color::new(1.0, 0.9, 0.8), 0.3)),
+            ),
+            (
+                "fresnel metal",
+                Arc::new(Metal::new(Color::new(1.0, 0.71, 0.29), 0.0).with_fresnel(true)),
+            ),
+            ("dielectric", Arc::new(Dielectric::new(1.5))),
+        ];
+        let mut rng = StdRng::seed_from_u64(4);
+        for (name, mat) in materials {
+            let albedo = material_albedo_estimate(mat.as_ref(), &mut rng, 10_000);
+            assert!(
+                albedo.max_component() <= 1.0 + 1.0e-9,
+                "{} gains energy, albedo {}",
+                name,
+                albedo
+            );
+        }
+    }
 }
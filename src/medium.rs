@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::material::{Isotropic, Scatter};
+
+use super::hit::{Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Color, Vec3};
+
+// Fog of uniform density filling a closed boundary. Rays scatter inside at an exponentially
+// distributed distance, or pass through when that lies beyond the far side.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hit>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Scatter>,
+}
+
+impl ConstantMedium {
+    pub fn new<H: Hit + 'static>(boundary: H, density: f64, albedo: Color) -> ConstantMedium {
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            neg_inv_density: -1.0 / density,
+            phase_function: Arc::new(Isotropic::new(albedo)),
+        }
+    }
+}
+
+impl Hit for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // Both crossings of the boundary along the whole line, then the part in range
+        let entry = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self.boundary.hit(r, entry.t + 0.0001, f64::INFINITY)?;
+        let t_enter = entry.t.max(t_min).max(0.0);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * ray_uniform(r).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        Some(HitRecord {
+            t,
+            p: r.at(t),
+            // Arbitrary, the isotropic phase function ignores it
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            mat: self.phase_function.clone(),
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        })
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.phase_function.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+// Uniform in (0, 1] hashed from the ray itself, as `hit` has no rng to draw from. Every sample
+// traces different rays, and renders with --seed stay reproducible.
+fn ray_uniform(r: &Ray) -> f64 {
+    let (o, d) = (r.origin(), r.direction());
+    let bits = [o.x(), o.y(), o.z(), d.x(), d.y(), d.z(), r.time()];
+    let hash = bits.iter().fold(0x9e37_79b9_7f4a_7c15_u64, |hash, x| {
+        // splitmix64 finalizer
+        let mut z = (hash ^ x.to_bits()).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    });
+    ((hash >> 11) + 1) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::cube::Cube;
+    use crate::material::Lambertian;
+    use crate::vec::Point3;
+
+    // A 2 unit deep box of fog straight ahead along -z
+    fn fog(density: f64) -> ConstantMedium {
+        let boundary = Cube::new(
+            Point3::new(-1.0, -1.0, -4.0),
+            Point3::new(1.0, 1.0, -2.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        ConstantMedium::new(boundary, density, Color::new(0.8, 0.8, 0.8))
+    }
+
+    #[test]
+    fn denser_fog_is_hit_more_often() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut last = 0.0;
+        for density in [0.1, 0.5, 2.0] {
+            let medium = fog(density);
+            let hits = (0..4000)
+                .filter(|_| {
+                    // Slightly different rays along the same line through the box
+                    let origin = Point3::new(0.0, 0.0, rng.gen_range(0.0..1.0));
+                    let r = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0), 0.0);
+                    match medium.hit(&r, 0.001, f64::INFINITY) {
+                        Some(rec) => {
+                            assert!((-4.0..=-2.0).contains(&rec.p.z()));
+                            true
+                        }
+                        None => false,
+                    }
+                })
+                .count();
+            // Beer-Lambert: 1 - exp(-density * depth)
+            let fraction = hits as f64 / 4000.0;
+            let expected = 1.0 - (-density * 2.0).exp();
+            assert!(
+                (fraction - expected).abs() < 0.03,
+                "density {}: {} of rays scattered, expected {}",
+                density,
+                fraction,
+                expected
+            );
+            assert!(fraction > last);
+            last = fraction;
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use crate::material::Scatter;
+use crate::triangle::Triangle;
+
+use super::vec::{Point3, Vec3};
+
+// Triangles of the `v`, `vn` and `f` statements of a Wavefront OBJ file, everything else is
+// skipped. Polygons are split into fans around their first vertex. Faces with a normal at
+// every corner are shaded smooth.
+pub fn load_obj(path: &str, mat: Arc<dyn Scatter>) -> io::Result<Vec<Triangle>> {
+    parse_obj(&fs::read_to_string(path)?, mat)
+}
+
+fn parse_obj(source: &str, mat: Arc<dyn Scatter>) -> io::Result<Vec<Triangle>> {
+    let invalid = |line: usize, msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", line, msg),
+        )
+    };
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut triangles = Vec::new();
+    for (idx, line) in source.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some(kind @ ("v" | "vn")) => {
+                let e: Vec<f64> = fields
+                    .take(3)
+                    .map(|field| field.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| invalid(idx + 1, format!("invalid {}: {}", kind, err)))?;
+                let e = match e[..] {
+                    [x, y, z] => Vec3::new(x, y, z),
+                    _ => return Err(invalid(idx + 1, "expected 3 coordinates".to_string())),
+                };
+                match kind {
+                    "v" => vertices.push(e),
+                    _ => normals.push(e.normalized()),
+                }
+            }
+            Some("f") => {
+                let face: Vec<(Point3, Option<Vec3>)> = fields
+                    .map(|field| corner(field, &vertices, &normals))
+                    .collect::<Result<_, _>>()
+                    .map_err(|msg| invalid(idx + 1, msg))?;
+                if face.len() < 3 {
+                    return Err(invalid(idx + 1, "expected at least 3 vertices".to_string()));
+                }
+                for pair in face[1..].windows(2) {
+                    let [(p0, n0), (p1, n1), (p2, n2)] = [face[0], pair[0], pair[1]];
+                    let triangle = Triangle::new(p0, p1, p2, mat.clone());
+                    triangles.push(match (n0, n1, n2) {
+                        (Some(n0), Some(n1), Some(n2)) => triangle.with_normals(n0, n1, n2),
+                        _ => triangle,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+// A face corner as in "3", "3/1", "3//2" or "3/1/2": the position and, when given, the normal.
+// Texture coordinates are not used.
+fn corner(
+    field: &str,
+    vertices: &[Point3],
+    normals: &[Vec3],
+) -> Result<(Point3, Option<Vec3>), String> {
+    let mut indices = field.split('/');
+    let position = resolve(indices.next().unwrap_or(field), vertices, "vertex")?;
+    let normal = match indices.nth(1) {
+        Some(index) if !index.is_empty() => Some(resolve(index, normals, "normal")?),
+        _ => None,
+    };
+    Ok((position, normal))
+}
+
+// Indices count from 1, negative ones back from the last element read so far
+fn resolve(index: &str, items: &[Vec3], what: &str) -> Result<Vec3, String> {
+    let resolved = match index.parse::<i64>() {
+        Ok(i) if i > 0 => Some(i as usize - 1),
+        Ok(i) if i < 0 => items.len().checked_sub(i.unsigned_abs() as usize),
+        _ => None,
+    };
+    resolved
+        .and_then(|i| items.get(i).copied())
+        .ok_or_else(|| format!("invalid {} index {:?}", what, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::surrounding_box;
+    use crate::hit::Hit;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::vec::Color;
+
+    fn grey() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    const CUBE: &str = "\
+# unit cube from (-1,-1,-1) to (1,1,1)
+o cube
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+vt 0 0
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    #[test]
+    fn cube_gives_twelve_triangles_inside_its_extent() {
+        let triangles = parse_obj(CUBE, grey()).unwrap();
+        assert_eq!(triangles.len(), 12);
+
+        let bbox = triangles
+            .iter()
+            .map(|t| t.bounding_box().unwrap())
+            .reduce(surrounding_box)
+            .unwrap();
+        assert!((bbox.minimum - Point3::new(-1.0, -1.0, -1.0)).length() < 1e-3);
+        assert!((bbox.maximum - Point3::new(1.0, 1.0, 1.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn quad_face_is_split_into_a_fan_covering_it() {
+        let quad = "v 0 0 0\nv 2 0 0\nv 2 2 0\nv 0 2 0\nf 1 2 3 4\n";
+        let triangles = parse_obj(quad, grey()).unwrap();
+        assert_eq!(triangles.len(), 2);
+
+        // Points on either side of the 1-3 diagonal are covered by exactly one triangle
+        for &(x, y) in &[(0.5, 1.5), (1.5, 0.5), (0.1, 0.3), (1.9, 1.7)] {
+            let r = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let hits = triangles
+                .iter()
+                .filter(|t| t.hit(&r, 0.001, f64::INFINITY).is_some())
+                .count();
+            assert_eq!(hits, 1, "at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let absolute = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n", grey()).unwrap();
+        let relative =
+            parse_obj("v 9 9 9\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n", grey()).unwrap();
+        let (a, b) = (
+            absolute[0].bounding_box().unwrap(),
+            relative[0].bounding_box().unwrap(),
+        );
+        assert!((a.minimum - b.minimum).length() < 1e-12);
+        assert!((a.maximum - b.maximum).length() < 1e-12);
+
+        // Relative to the vertices read so far, not the whole file
+        let early = parse_obj("v 0 0 0\nv 1 0 0\nf -2 -1 -3\nv 0 1 0\n", grey());
+        assert!(early.is_err());
+        let beyond = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n", grey());
+        assert!(beyond.is_err());
+    }
+}
@@ -0,0 +1,433 @@
+use std::io::{self, Read, Write};
+
+use crate::postprocess::{average, tonemap_and_encode, ToneMap};
+
+use super::vec::Color;
+
+// Plain PPM (P3) for RGB, or binary PAM (P7) when an alpha channel is requested
+pub struct ImageWriter<W: Write> {
+    out: W,
+    samples_per_pixel: u64,
+    alpha: bool,
+    tonemap: ToneMap,
+    gamma: f64,
+}
+
+impl<W: Write> ImageWriter<W> {
+    pub fn new(
+        mut out: W,
+        width: u64,
+        height: u64,
+        samples_per_pixel: u64,
+        alpha: bool,
+    ) -> io::Result<ImageWriter<W>> {
+        if alpha {
+            writeln!(out, "P7")?;
+            writeln!(out, "WIDTH {}", width)?;
+            writeln!(out, "HEIGHT {}", height)?;
+            writeln!(out, "DEPTH 4")?;
+            writeln!(out, "MAXVAL 255")?;
+            writeln!(out, "TUPLTYPE RGB_ALPHA")?;
+            writeln!(out, "ENDHDR")?;
+        } else {
+            writeln!(out, "P3")?;
+            writeln!(out, "{} {}", width, height)?;
+            writeln!(out, "255")?;
+        }
+
+        Ok(ImageWriter {
+            out,
+            samples_per_pixel,
+            alpha,
+            tonemap: ToneMap::Clamp,
+            gamma: 2.0,
+        })
+    }
+
+    pub fn with_tonemap(self, tonemap: ToneMap, gamma: f64) -> ImageWriter<W> {
+        ImageWriter {
+            tonemap,
+            gamma,
+            ..self
+        }
+    }
+
+    // `coverage` is the fraction of samples that hit geometry, ignored without alpha
+    pub fn write_pixel(&mut self, pixel_color: Color, coverage: f64) -> io::Result<()> {
+        let [r, g, b] = tonemap_and_encode(
+            pixel_color,
+            self.samples_per_pixel,
+            self.tonemap,
+            self.gamma,
+        );
+        if self.alpha {
+            let a = (255.0 * coverage.clamp(0.0, 1.0)).round() as u8;
+            self.out.write_all(&[r, g, b, a])
+        } else {
+            writeln!(self.out, "{} {} {}", r, g, b)
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// 8-bit RGB PNG, `pixels` are sums in top to bottom order like PNG rows. Deflate uses
+// stored blocks only: no compression, but no dependency and far smaller than ASCII PPM.
+pub fn write_png<W: Write>(
+    mut out: W,
+    width: u64,
+    height: u64,
+    samples_per_pixel: u64,
+    tonemap: ToneMap,
+    gamma: f64,
+    pixels: &[Color],
+) -> io::Result<()> {
+    // Each row starts with filter type 0, none
+    let mut raw = Vec::with_capacity(((3 * width + 1) * height) as usize);
+    for row in pixels.chunks(width as usize) {
+        raw.push(0);
+        for pixel_color in row {
+            raw.extend(tonemap_and_encode(
+                *pixel_color,
+                samples_per_pixel,
+                tonemap,
+                gamma,
+            ));
+        }
+    }
+
+    // zlib header for deflate with a 32K window, no preset dictionary
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(u16::MAX as usize);
+    let last = blocks.len() - 1;
+    for (idx, block) in blocks.enumerate() {
+        let len = block.len() as u16;
+        zlib.push((idx == last) as u8);
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // Bit depth 8, color type 2 (RGB), default compression, filter and no interlace
+    header.extend([8, 2, 0, 0, 0]);
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_png_chunk(&mut out, b"IHDR", &header)?;
+    write_png_chunk(&mut out, b"IDAT", &zlib)?;
+    write_png_chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+}
+
+// Reads back what write_png writes: 8-bit RGB, no interlace, unfiltered rows in stored
+// deflate blocks. Anything else is reported as unsupported rather than decoded.
+pub fn read_png<R: Read>(mut input: R) -> io::Result<(u64, u64, Vec<[u8; 3]>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err(invalid("not a PNG file"));
+    }
+
+    let (mut header, mut zlib) = (None, Vec::new());
+    let mut rest = &bytes[8..];
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() < len + 12 {
+            return Err(invalid("truncated chunk"));
+        }
+        let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+        match kind {
+            b"IHDR" => header = Some(data.to_vec()),
+            b"IDAT" => zlib.extend(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = &rest[len + 12..];
+    }
+    let header = header.ok_or_else(|| invalid("no IHDR chunk"))?;
+    if header.len() != 13 || header[8..] != [8, 2, 0, 0, 0] {
+        return Err(invalid("only 8-bit RGB without interlace is supported"));
+    }
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as u64;
+
+    // Skip the zlib header, then copy the stored blocks up to the final one
+    let mut raw = Vec::new();
+    let mut pos = 2;
+    loop {
+        let block = zlib
+            .get(pos..pos + 5)
+            .ok_or_else(|| invalid("truncated deflate"))?;
+        if block[0] & 0b110 != 0 {
+            return Err(invalid("only stored deflate blocks are supported"));
+        }
+        let len = u16::from_le_bytes([block[1], block[2]]) as usize;
+        let data = zlib
+            .get(pos + 5..pos + 5 + len)
+            .ok_or_else(|| invalid("truncated deflate"))?;
+        raw.extend(data);
+        pos += 5 + len;
+        if block[0] & 1 == 1 {
+            break;
+        }
+    }
+
+    let stride = (3 * width + 1) as usize;
+    if raw.len() != stride * height as usize {
+        return Err(invalid("image data does not match its size"));
+    }
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for row in raw.chunks(stride) {
+        if row[0] != 0 {
+            return Err(invalid("only unfiltered rows are supported"));
+        }
+        pixels.extend(row[1..].chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]));
+    }
+    Ok((width, height, pixels))
+}
+
+fn write_png_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data));
+    out.write_all(&crc.to_be_bytes())
+}
+
+// CRC-32 as used by PNG, reflected polynomial 0xedb88320
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % MOD_ADLER;
+        b = (b + a) % MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+// Linear float PFM, `pixels` are sums in top to bottom order while PFM stores bottom to top
+pub fn write_pfm<W: Write>(
+    mut out: W,
+    width: u64,
+    height: u64,
+    samples_per_pixel: u64,
+    pixels: &[Color],
+) -> io::Result<()> {
+    writeln!(out, "PF")?;
+    writeln!(out, "{} {}", width, height)?;
+    // Negative scale marks little-endian samples
+    writeln!(out, "-1.0")?;
+
+    for row in pixels.chunks(width as usize).rev() {
+        for pixel_color in row {
+            let color = average(*pixel_color, samples_per_pixel);
+            for channel in 0..3 {
+                out.write_all(&(color[channel] as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+// Radiance RGBE, the averaged linear radiance with no clamping or gamma. Scanlines are
+// stored flat, top to bottom like `pixels`; every reader takes them, run-length encoding
+// is optional.
+pub fn write_hdr<W: Write>(
+    mut out: W,
+    width: u64,
+    height: u64,
+    samples_per_pixel: u64,
+    pixels: &[Color],
+) -> io::Result<()> {
+    writeln!(out, "#?RADIANCE")?;
+    writeln!(out, "FORMAT=32-bit_rle_rgbe")?;
+    writeln!(out)?;
+    writeln!(out, "-Y {} +X {}", height, width)?;
+
+    for pixel_color in pixels {
+        out.write_all(&rgbe(average(*pixel_color, samples_per_pixel)))?;
+    }
+    out.flush()
+}
+
+// Three 8-bit mantissas sharing the exponent of the largest component. Negative components
+// have no encoding and become zero.
+fn rgbe(color: Color) -> [u8; 4] {
+    let brightest = color.max_component().min(f64::MAX);
+    if brightest.is_nan() || brightest <= 1.0e-32 {
+        return [0; 4];
+    }
+    // The exponent puts the brightest component's mantissa in [0.5, 1)
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let scale = 256.0 / 2f64.powi(exponent);
+    let mantissa = |c: f64| (c.max(0.0) * scale).min(255.0) as u8;
+    [
+        mantissa(color[0]),
+        mantissa(color[1]),
+        mantissa(color[2]),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hit::WorldBuilder;
+    use crate::material::DiffuseLight;
+    use crate::render::{render, Settings};
+    use crate::vec::Point3;
+
+    // Flat RGBE scanlines as write_hdr produces them, back to linear colours
+    fn read_hdr(bytes: &[u8]) -> (u64, u64, Vec<Color>) {
+        let header_end = bytes.windows(2).position(|w| w == b"\n\n").unwrap() + 2;
+        assert!(bytes.starts_with(b"#?RADIANCE\n"));
+        let size_end = header_end
+            + bytes[header_end..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .unwrap();
+        let size = std::str::from_utf8(&bytes[header_end..size_end]).unwrap();
+        let fields: Vec<&str> = size.split_whitespace().collect();
+        assert_eq!((fields[0], fields[2]), ("-Y", "+X"));
+        let (height, width) = (fields[1].parse().unwrap(), fields[3].parse().unwrap());
+
+        let pixels = bytes[size_end + 1..]
+            .chunks(4)
+            .map(|rgbe| {
+                if rgbe[3] == 0 {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                let scale = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+                let channel = |m: u8| (m as f64 + 0.5) * scale;
+                Color::new(channel(rgbe[0]), channel(rgbe[1]), channel(rgbe[2]))
+            })
+            .collect();
+        (width, height, pixels)
+    }
+
+    // The little-endian float PFM write_pfm produces, rows back in top to bottom order
+    fn read_pfm(bytes: &[u8]) -> (u64, u64, Vec<Color>) {
+        let mut lines = bytes.splitn(4, |b| *b == b'\n');
+        assert_eq!(lines.next().unwrap(), b"PF");
+        let size = std::str::from_utf8(lines.next().unwrap()).unwrap();
+        let (width, height) = size.split_once(' ').unwrap();
+        let (width, height): (u64, u64) = (width.parse().unwrap(), height.parse().unwrap());
+        assert_eq!(lines.next().unwrap(), b"-1.0");
+
+        let floats: Vec<f64> = lines
+            .next()
+            .unwrap()
+            .chunks(4)
+            .map(|le| f32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64)
+            .collect();
+        let rows: Vec<Vec<Color>> = floats
+            .chunks(3 * width as usize)
+            .map(|row| {
+                row.chunks(3)
+                    .map(|c| Color::new(c[0], c[1], c[2]))
+                    .collect()
+            })
+            .collect();
+        (width, height, rows.into_iter().rev().flatten().collect())
+    }
+
+    #[test]
+    fn one_render_writes_a_display_png_and_a_linear_pfm() {
+        // Sky above, and a light bright enough to go past display white
+        let world = WorldBuilder::new()
+            .sphere(
+                Point3::new(0.0, -0.4, -1.5),
+                0.6,
+                Arc::new(DiffuseLight::new(Color::new(4.0, 2.0, 1.0))),
+            )
+            .build();
+        let cam = CameraBuilder::new().aspect_ratio(1.5).build();
+        let settings = Settings {
+            seed: Some(2),
+            ..Settings::new(6, 4, 3)
+        };
+        let image = render(&world, &cam, &settings);
+        let sums: Vec<Color> = image.pixels.iter().map(|pixel| pixel.color).collect();
+
+        let (mut png, mut pfm) = (Vec::new(), Vec::new());
+        write_png(&mut png, 6, 4, 3, ToneMap::Clamp, 2.0, &sums).unwrap();
+        write_pfm(&mut pfm, 6, 4, 3, &sums).unwrap();
+        let (png_width, png_height, display) = read_png(png.as_slice()).unwrap();
+        let (pfm_width, pfm_height, linear) = read_pfm(&pfm);
+        assert_eq!((png_width, png_height, pfm_width, pfm_height), (6, 4, 6, 4));
+
+        for ((sum, display), linear) in sums.iter().zip(&display).zip(&linear) {
+            let radiance = average(*sum, 3);
+            assert_eq!(*display, tonemap_and_encode(*sum, 3, ToneMap::Clamp, 2.0));
+            for channel in 0..3 {
+                assert_eq!(linear[channel], radiance[channel] as f32 as f64);
+            }
+        }
+        // The light clips to white in the PNG but keeps its radiance in the PFM
+        let brightest = linear.iter().map(|c| c.x()).fold(0.0, f64::max);
+        assert!(brightest > 2.0, "{}", brightest);
+        assert!(display.iter().any(|rgb| rgb[0] == 255));
+        // And the sky is at the top of both
+        assert!(linear[0].z() > linear[0].x() && display[0][2] > display[0][0]);
+    }
+
+    #[test]
+    fn hdr_keeps_values_above_one() {
+        let colors = [
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(0.25, 0.5, 0.75),
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(2.29, 0.1, 1.5),
+            Color::new(15.0, 40.0, 3.0),
+            Color::new(1000.0, 0.0, 0.001),
+        ];
+        // Sums of four samples each
+        let sums: Vec<Color> = colors.iter().map(|c| 4.0 * *c).collect();
+        let mut bytes = Vec::new();
+        write_hdr(&mut bytes, 3, 2, 4, &sums).unwrap();
+
+        let (width, height, decoded) = read_hdr(&bytes);
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(decoded.len(), colors.len());
+        for (original, decoded) in colors.iter().zip(&decoded) {
+            // Each channel is within one mantissa step of the brightest one
+            let step = original.max_component() / 128.0;
+            for channel in 0..3 {
+                assert!(
+                    (original[channel] - decoded[channel]).abs() <= step,
+                    "{} came back as {}",
+                    original,
+                    decoded
+                );
+            }
+        }
+        assert!(decoded[3].x() > 2.2 && decoded[4].y() > 39.0 && decoded[5].x() > 990.0);
+    }
+}
@@ -0,0 +1,97 @@
+use rand::{seq::SliceRandom, RngCore};
+
+use super::vec::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise over a lattice of random unit vectors, in -1..1
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(rng: &mut dyn RngCore) -> Perlin {
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| Vec3::random(-1.0..1.0, rng).normalized())
+            .collect();
+        let mut permute = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (permute(), permute(), permute());
+        Perlin {
+            ranvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (u, v, w) = (
+            p.x() - p.x().floor(),
+            p.y() - p.y().floor(),
+            p.z() - p.z().floor(),
+        );
+        let (i, j, k) = (
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
+        );
+
+        let wrap = |n: i64, d: usize| (n + d as i64).rem_euclid(POINT_COUNT as i64) as usize;
+
+        // Gradients at the eight corners of the lattice cell around p
+        let mut c = [[[Vec3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.ranvec[self.perm_x[wrap(i, di)]
+                        ^ self.perm_y[wrap(j, dj)]
+                        ^ self.perm_z[wrap(k, dk)]];
+                }
+            }
+        }
+        trilinear_interp(&c, u, v, w)
+    }
+
+    // Sum of `depth` octaves, each at twice the frequency and half the weight of the last
+    pub fn turb(&self, p: &Point3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+// Blend of the corner gradients' dot products, eased with a Hermite cubic to hide the lattice
+fn trilinear_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+    let (uu, vv, ww) = (
+        u * u * (3.0 - 2.0 * u),
+        v * v * (3.0 - 2.0 * v),
+        w * w * (3.0 - 2.0 * w),
+    );
+    let mut accum = 0.0;
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                let weight = Vec3::new(u - fi, v - fj, w - fk);
+                accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                    * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                    * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                    * corner.dot(weight);
+            }
+        }
+    }
+    accum
+}
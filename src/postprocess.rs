@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use super::vec::Color;
+
+// How averaged radiance is squeezed into the displayable 0..1 range, per channel
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // Clip at 1.0, bright highlights blow out
+    Clamp,
+    // c / (1 + c), approaches but never reaches white
+    Reinhard,
+    // Reinhard that maps the given value to exactly 1.0
+    ReinhardExtended(f64),
+}
+
+// Extended Reinhard takes a white point and comes from --white-point instead
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            _ => Err(format!("unknown tone map {:?}", s)),
+        }
+    }
+}
+
+impl ToneMap {
+    fn apply(self, color: Color) -> Color {
+        let map = |c: f64| match self {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard => c / (1.0 + c),
+            ToneMap::ReinhardExtended(white) => c * (1.0 + c / white.powi(2)) / (1.0 + c),
+        };
+        Color::new(map(color[0]), map(color[1]), map(color[2]))
+    }
+}
+
+// Linear radiance of a pixel from the sum of its samples, what every output format starts from
+pub fn average(color: Color, samples: u64) -> Color {
+    color / samples as f64
+}
+
+// Average of `samples` summed samples, tone mapped, gamma encoded and quantized to 8 bits
+pub fn tonemap_and_encode(color: Color, samples: u64, tonemap: ToneMap, gamma: f64) -> [u8; 3] {
+    let color = tonemap.apply(average(color, samples));
+    let encode = |c: f64| {
+        // sqrt rather than powf for the default gamma 2, to stay bit for bit with older output
+        let c = if gamma == 2.0 {
+            c.sqrt()
+        } else {
+            c.powf(1.0 / gamma)
+        };
+        (256.0 * c.clamp(0.0, 0.999)) as u8
+    };
+    [encode(color[0]), encode(color[1]), encode(color[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // What the PPM writer did before tone mapping existed
+    fn legacy(color: Color, samples_per_pixel: u64) -> [u8; 3] {
+        let scale = 1.0 / samples_per_pixel as f64;
+        let ir = (256.0 * (color[0] * scale).sqrt().clamp(0.0, 0.999)) as u8;
+        let ig = (256.0 * (color[1] * scale).sqrt().clamp(0.0, 0.999)) as u8;
+        let ib = (256.0 * (color[2] * scale).sqrt().clamp(0.0, 0.999)) as u8;
+        [ir, ig, ib]
+    }
+
+    #[test]
+    fn clamp_with_gamma_2_matches_the_legacy_encoding() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for samples in [1, 3, 7, 10, 100, 1000] {
+            // Random sums, and sums right at the edges between two 8-bit levels
+            let random = (0..10_000).map(|_| samples as f64 * rng.gen_range(0.0..1.5));
+            let edges = (0..=256).map(|level| samples as f64 * (level as f64 / 256.0).powi(2));
+            for sum in random.chain(edges) {
+                let color = Color::new(sum, sum * (1.0 + f64::EPSILON), -sum);
+                assert_eq!(
+                    tonemap_and_encode(color, samples, ToneMap::Clamp, 2.0),
+                    legacy(color, samples),
+                    "{} over {} samples",
+                    color,
+                    samples
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reinhard_keeps_bright_values_below_white() {
+        for c in [0.0, 0.5, 1.0, 10.0, 1.0e3, 1.0e6, 1.0e12] {
+            let mapped = ToneMap::Reinhard.apply(Color::new(c, c, c));
+            assert!(
+                (0.0..1.0).contains(&mapped[0]),
+                "{} maps to {}",
+                c,
+                mapped[0]
+            );
+        }
+        // Monotonic, so brighter stays brighter
+        let dim = ToneMap::Reinhard.apply(Color::new(10.0, 0.0, 0.0));
+        let bright = ToneMap::Reinhard.apply(Color::new(100.0, 0.0, 0.0));
+        assert!(dim[0] < bright[0]);
+
+        // Where Clamp saturates every channel, Reinhard still tells them apart
+        let hdr = Color::new(2.0, 20.0, 200.0);
+        let [r, g, b] = tonemap_and_encode(hdr, 1, ToneMap::Reinhard, 2.0);
+        assert!(r < g && g < b, "{:?}", [r, g, b]);
+        assert_eq!(tonemap_and_encode(hdr, 1, ToneMap::Clamp, 2.0), [255; 3]);
+
+        // The extended variant reaches exactly 1 at its white point
+        let white = ToneMap::ReinhardExtended(4.0).apply(Color::new(4.0, 4.0, 4.0));
+        assert!((white[0] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn higher_white_point_keeps_more_highlight_detail() {
+        // Highlights between 2 and 8 times the display white, one per channel
+        let highlights = Color::new(2.0, 4.0, 8.0);
+        let distinct = |white: f64| {
+            let [r, g, b] =
+                tonemap_and_encode(highlights, 1, ToneMap::ReinhardExtended(white), 2.0);
+            (r < g) as u32 + (g < b) as u32
+        };
+        assert_eq!(distinct(1.0), 0);
+        assert_eq!(distinct(4.0), 1);
+        assert_eq!(distinct(16.0), 2);
+
+        // An infinite white point is plain Reinhard
+        let color = Color::new(0.3, 3.0, 30.0);
+        assert_eq!(
+            tonemap_and_encode(color, 1, ToneMap::ReinhardExtended(f64::INFINITY), 2.0),
+            tonemap_and_encode(color, 1, ToneMap::Reinhard, 2.0)
+        );
+    }
+}
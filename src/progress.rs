@@ -0,0 +1,97 @@
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 30;
+// How often a terminal bar redraws, and how often plain lines go to logs and pipes
+const TTY_INTERVAL: Duration = Duration::from_millis(100);
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+// Progress of a render on stderr, counted in whatever units the caller finishes: a bar with
+// elapsed time and ETA on a terminal, an occasional plain line otherwise. Counts silently
+// when not `shown`.
+pub struct Progress {
+    total: usize,
+    shown: bool,
+    done: AtomicUsize,
+    start: Instant,
+    tty: bool,
+    // Milliseconds since start at the last report, the thread that moves it reports
+    last_report: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(total: usize, shown: bool) -> Progress {
+        Progress {
+            total,
+            shown,
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+            tty: io::stderr().is_terminal(),
+            last_report: AtomicU64::new(0),
+        }
+    }
+
+    // One more unit done, callable from any number of threads at once
+    pub fn inc(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if !self.shown {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+        let interval = if self.tty { TTY_INTERVAL } else { LOG_INTERVAL };
+        let now = elapsed.as_millis() as u64;
+        let last = self.last_report.load(Ordering::Relaxed);
+        if now >= last + interval.as_millis() as u64
+            && self
+                .last_report
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.report(done, elapsed);
+        }
+    }
+
+    fn report(&self, done: usize, elapsed: Duration) {
+        let fraction = (done as f64 / self.total as f64).min(1.0);
+        let eta = elapsed.mul_f64((1.0 - fraction) / fraction);
+        let status = format!(
+            "{:3.0}% {} elapsed, ETA {}",
+            100.0 * fraction,
+            clock(elapsed),
+            clock(eta)
+        );
+        if self.tty {
+            let filled = (fraction * BAR_WIDTH as f64) as usize;
+            eprint!(
+                "\x1b[2K\r[{}{}] {}",
+                "=".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled),
+                status
+            );
+        } else {
+            eprintln!("{}", status);
+        }
+    }
+
+    // Wipes the bar on a terminal, so whatever comes next starts on a clean line
+    pub fn finish(self) {
+        if !self.shown {
+            return;
+        }
+        if self.tty {
+            eprint!("\x1b[2K\r");
+        } else {
+            eprintln!("100% done in {}", clock(self.start.elapsed()));
+        }
+    }
+}
+
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
@@ -0,0 +1,166 @@
+use rand::{Rng, RngCore};
+use std::sync::Arc;
+
+use crate::aabb::{Aabb, BOX_PADDING};
+use crate::material::Scatter;
+
+use super::hit::{planar_pdf_value, Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Point3, Vec3};
+
+// Parallelogram with a corner at `q` and edges `u` and `v` from it, in any orientation.
+// Faces along u x v, so counter-clockwise edges seen from the front.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    // Plane offset, normal . p = d for every point p on the plane
+    d: f64,
+    // (u x v) / |u x v|^2, turns an in-plane offset into edge coordinates
+    w: Vec3,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Scatter>) -> Quad {
+        let n = u.cross(v);
+        let normal = n.normalized();
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(q),
+            w: n / n.length_squared(),
+            mat,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        self.u.cross(self.v).length()
+    }
+}
+
+impl Hit for Quad {
+    // The plane first, then where the point lies along the two edges
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        const EPS: f64 = 1.0e-12;
+        let denom = self.normal.dot(r.direction());
+        if denom.abs() < EPS {
+            // Ray parallel to the plane
+            return None;
+        }
+        let t = (self.d - self.normal.dot(r.origin())) / denom;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut rec = HitRecord {
+            t,
+            p,
+            mat: self.mat.clone(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            u: alpha,
+            v: beta,
+            front_face: false,
+        };
+        rec.set_face_normal(r, self.normal);
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let (mut minimum, mut maximum) = (self.q, self.q);
+        for corner in corners.iter() {
+            for axis in 0..3 {
+                minimum[axis] = minimum[axis].min(corner[axis]);
+                maximum[axis] = maximum[axis].max(corner[axis]);
+            }
+        }
+        let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Some(Aabb::new(minimum - padding, maximum + padding))
+    }
+
+    // Uniform over the area, converted to solid angle as seen from `origin`
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        planar_pdf_value(self, self.normal, self.area(), origin, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = self.q + rng.gen::<f64>() * self.u + rng.gen::<f64>() * self.v;
+        (point - origin).normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::Color;
+
+    fn grey() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // Straight down -z from (x, y, 0)
+    fn down(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn interior_hit_gives_the_edge_coordinates() {
+        // Skewed, so alpha and beta are not just x and y
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, -3.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.5, 2.0, 0.0),
+            grey(),
+        );
+        let target = Point3::new(-1.0, -1.0, -3.0)
+            + 0.25 * Vec3::new(2.0, 0.0, 0.0)
+            + 0.5 * Vec3::new(0.5, 2.0, 0.0);
+        let rec = quad
+            .hit(&down(target.x(), target.y()), 0.001, f64::INFINITY)
+            .unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn edges_are_inside_and_beyond_them_is_a_miss() {
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey(),
+        );
+        let alpha = |x: f64| {
+            quad.hit(&down(x, 0.0), 0.001, f64::INFINITY)
+                .map(|rec| rec.u)
+        };
+        assert_eq!(alpha(-1.0), Some(0.0));
+        assert_eq!(alpha(1.0), Some(1.0));
+        assert_eq!(alpha(1.001), None);
+        assert_eq!(alpha(-1.001), None);
+        assert!(quad.hit(&down(0.0, 1.5), 0.001, f64::INFINITY).is_none());
+        assert_eq!(
+            quad.pdf_value(Point3::new(0.0, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+            0.0
+        );
+    }
+}
@@ -1,15 +1,24 @@
 use super::vec::{Point3, Vec3};
 
+// Hits closer than this to a ray's origin are ignored, so rays leaving a surface do not
+// find it again through rounding error (shadow acne). --normal-offset does without it.
+pub const T_MIN: f64 = 0.001;
+// Rays from the camera and bounces look arbitrarily far
+pub const T_MAX: f64 = f64::INFINITY;
+
+#[derive(Clone, Copy)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
+    tm: f64,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vec3) -> Ray {
+    pub fn new(origin: Point3, direction: Vec3, time: f64) -> Ray {
         Ray {
             orig: origin,
             dir: direction,
+            tm: time,
         }
     }
 
@@ -21,6 +30,10 @@ impl Ray {
         self.dir
     }
 
+    pub fn time(&self) -> f64 {
+        self.tm
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
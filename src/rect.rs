@@ -0,0 +1,221 @@
+use rand::{Rng, RngCore};
+use std::sync::Arc;
+
+use crate::aabb::{Aabb, BOX_PADDING};
+use crate::material::Scatter;
+
+use super::hit::{planar_pdf_value, Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Point3, Vec3};
+
+pub type XyRect = Rect<2>;
+pub type XzRect = Rect<1>;
+pub type YzRect = Rect<0>;
+
+// Rectangle in the plane where coordinate AXIS equals `k`, bounded along the other two
+// axes in ascending order, e.g. x then z for an XzRect. Faces the positive AXIS direction.
+pub struct Rect<const AXIS: usize> {
+    a0: f64,
+    a1: f64,
+    b0: f64,
+    b1: f64,
+    k: f64,
+    mat: Arc<dyn Scatter>,
+}
+
+impl<const AXIS: usize> Rect<AXIS> {
+    const A: usize = if AXIS == 0 { 1 } else { 0 };
+    const B: usize = if AXIS == 2 { 1 } else { 2 };
+
+    // Panics unless a0 < a1 and b0 < b1, light sampling picks points in between
+    pub fn new(a0: f64, a1: f64, b0: f64, b1: f64, k: f64, mat: Arc<dyn Scatter>) -> Rect<AXIS> {
+        assert!(
+            a0 < a1 && b0 < b1,
+            "rectangle bounds must be ascending, got {}..{} and {}..{}",
+            a0,
+            a1,
+            b0,
+            b1
+        );
+        Rect {
+            a0,
+            a1,
+            b0,
+            b1,
+            k,
+            mat,
+        }
+    }
+
+    fn normal() -> Vec3 {
+        let mut normal = Vec3::new(0.0, 0.0, 0.0);
+        normal[AXIS] = 1.0;
+        normal
+    }
+
+    fn area(&self) -> f64 {
+        (self.a1 - self.a0) * (self.b1 - self.b0)
+    }
+}
+
+impl<const AXIS: usize> Hit for Rect<AXIS> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // NaN for rays parallel to the plane, which fails the range check as well
+        let t = (self.k - r.origin()[AXIS]) / r.direction()[AXIS];
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        let p = r.at(t);
+        if !(self.a0..=self.a1).contains(&p[Self::A]) || !(self.b0..=self.b1).contains(&p[Self::B])
+        {
+            return None;
+        }
+
+        let mut rec = HitRecord {
+            t,
+            p,
+            mat: self.mat.clone(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+        };
+        rec.set_face_normal(r, Self::normal());
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut minimum = Point3::new(0.0, 0.0, 0.0);
+        let mut maximum = Point3::new(0.0, 0.0, 0.0);
+        minimum[Self::A] = self.a0;
+        maximum[Self::A] = self.a1;
+        minimum[Self::B] = self.b0;
+        maximum[Self::B] = self.b1;
+        minimum[AXIS] = self.k - BOX_PADDING;
+        maximum[AXIS] = self.k + BOX_PADDING;
+        Some(Aabb::new(minimum, maximum))
+    }
+
+    // Uniform over the area, converted to solid angle as seen from `origin`
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        planar_pdf_value(self, Self::normal(), self.area(), origin, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let mut point = Point3::new(0.0, 0.0, 0.0);
+        point[Self::A] = rng.gen_range(self.a0..self.a1);
+        point[Self::B] = rng.gen_range(self.b0..self.b1);
+        point[AXIS] = self.k;
+        (point - origin).normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::vec::Color;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn grey() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // A point with the given in-plane coordinates, `height` along the rectangle's own axis
+    fn point<const AXIS: usize>(a: f64, b: f64, height: f64) -> Point3 {
+        let mut p = Point3::new(0.0, 0.0, 0.0);
+        p[Rect::<AXIS>::A] = a;
+        p[Rect::<AXIS>::B] = b;
+        p[AXIS] = height;
+        p
+    }
+
+    fn check_hits_inside_only<const AXIS: usize>() {
+        let rect = Rect::<AXIS>::new(1.0, 2.0, 3.0, 5.0, 2.0, grey());
+        let down = -Rect::<AXIS>::normal();
+
+        // Straight down onto the front face
+        let r = Ray::new(point::<AXIS>(1.5, 4.0, 10.0), down, 0.0);
+        let rec = rect.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 8.0).abs() < 1e-12);
+        assert!((rec.p - point::<AXIS>(1.5, 4.0, 2.0)).length() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Rect::<AXIS>::normal()).length() < 1e-12);
+
+        // From behind the normal is flipped to face the ray
+        let r = Ray::new(point::<AXIS>(1.5, 4.0, -1.0), -down, 0.0);
+        let rec = rect.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.normal - down).length() < 1e-12);
+
+        // Outside the bounds along either in-plane axis
+        for (a, b) in [(0.5, 4.0), (2.5, 4.0), (1.5, 2.5), (1.5, 5.5)] {
+            let r = Ray::new(point::<AXIS>(a, b, 10.0), down, 0.0);
+            assert!(rect.hit(&r, 0.001, f64::INFINITY).is_none());
+        }
+    }
+
+    #[test]
+    fn xy_rect_is_hit_inside_its_bounds_only() {
+        check_hits_inside_only::<2>();
+    }
+
+    #[test]
+    fn xz_rect_is_hit_inside_its_bounds_only() {
+        check_hits_inside_only::<1>();
+        // The same for the rectangle spelled out in x and z
+        let rect = XzRect::new(-1.0, 1.0, -2.0, 0.5, 3.0, grey());
+        let r = Ray::new(Point3::new(0.5, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let rec = rect.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.p - Point3::new(0.5, 3.0, -1.0)).length() < 1e-12);
+        let r = Ray::new(Point3::new(0.5, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert!(rect.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn yz_rect_is_hit_inside_its_bounds_only() {
+        check_hits_inside_only::<0>();
+    }
+
+    #[test]
+    fn pdf_matches_the_same_quad() {
+        let rect = XzRect::new(-1.0, 1.0, -2.0, 0.5, 3.0, grey());
+        let quad = Quad::new(
+            Point3::new(-1.0, 3.0, -2.0),
+            Vec3::new(0.0, 0.0, 2.5),
+            Vec3::new(2.0, 0.0, 0.0),
+            grey(),
+        );
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let above = Vec3::new(0.0, 1.0, 0.0);
+        assert!((rect.pdf_value(origin, above) - 9.0 / 5.0).abs() < 1e-12);
+
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..100 {
+            let direction = rect.random(origin, &mut rng);
+            let (a, b) = (
+                rect.pdf_value(origin, direction),
+                quad.pdf_value(origin, direction),
+            );
+            assert!(a > 0.0 && (a - b).abs() < 1e-9 * a);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "ascending")]
+    fn empty_bounds_are_rejected() {
+        XzRect::new(1.0, 1.0, 0.0, 1.0, 0.0, grey());
+    }
+
+    #[test]
+    #[should_panic(expected = "ascending")]
+    fn reversed_bounds_are_rejected() {
+        XyRect::new(0.0, 1.0, 2.0, -2.0, 0.0, grey());
+    }
+}
@@ -0,0 +1,1329 @@
+use rand::{rngs::StdRng, thread_rng, Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use std::io;
+use std::str::FromStr;
+
+use crate::camera::Camera;
+use crate::checkpoint::Checkpoint;
+use crate::hit::{equirect_uv, Background, Hit, HitRecord, World};
+use crate::interrupt;
+use crate::material::{diffuse_bounce, MaterialKind};
+use crate::progress::Progress;
+use crate::stats;
+
+use super::ray::{Ray, T_MAX, T_MIN};
+use super::vec::{Color, Vec3};
+
+// Distance the scattered ray origin is pushed off the surface in normal-offset mode
+const NORMAL_OFFSET: f64 = 1.0e-4;
+// Deterministic glass splits per camera ray before falling back to a random choice
+const MAX_SPLITS: u32 = 3;
+// Half-width, in direction y, of the band where ground and sky blend
+const HORIZON_BLEND: f64 = 0.01;
+// Luminance step to a neighbour that marks a pixel for refinement in edge AA
+const EDGE_THRESHOLD: f64 = 0.1;
+// Bounces every path gets before Russian roulette may end it
+const ROULETTE_MIN_BOUNCES: u32 = 3;
+// Rows each thread gets per block under --stream, enough to even out slow rows
+const STREAM_ROWS_PER_THREAD: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Beauty,
+    // Heatmap of how many times a single path per pixel bounced before terminating
+    Bounces,
+    // Grayscale distance of the first hit from the focus plane, black is in focus
+    Coc,
+    // Shading normal of the first hit, mapped from [-1, 1] to [0, 1] per channel
+    Normals,
+    // Bounding boxes in place of the objects, a color per object for the first box entered
+    Aabb,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "beauty" => Ok(Mode::Beauty),
+            "bounces" => Ok(Mode::Bounces),
+            "coc" => Ok(Mode::Coc),
+            "normals" => Ok(Mode::Normals),
+            "aabb" => Ok(Mode::Aabb),
+            _ => Err(format!("unknown mode {:?}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum EdgeDetect {
+    // Large luminance step to a neighbour in the base pass
+    Luminance,
+    // Pixel corners see different objects, or an object and the background
+    Coverage,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sampling {
+    // Independent uniform offsets within the pixel
+    Random,
+    // One jittered offset per cell of a grid over the pixel
+    Stratified,
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sampling, String> {
+        match s {
+            "random" => Ok(Sampling::Random),
+            "stratified" => Ok(Sampling::Stratified),
+            _ => Err(format!("unknown sampling {:?}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NormalSpace {
+    World,
+    // x right, y up, z towards the viewer
+    Camera,
+}
+
+impl FromStr for NormalSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NormalSpace, String> {
+        match s {
+            "world" => Ok(NormalSpace::World),
+            "camera" => Ok(NormalSpace::Camera),
+            _ => Err(format!("unknown normal space {:?}", s)),
+        }
+    }
+}
+
+// Everything about how a frame is rendered apart from the world and the camera
+#[derive(Clone)]
+pub struct Settings {
+    pub width: u64,
+    pub height: u64,
+    // Debug modes other than Beauty want a single sample
+    pub samples_per_pixel: u64,
+    pub max_depth: u64,
+    // Makes renders reproducible, fresh entropy every run without it
+    pub seed: Option<u64>,
+    pub sampling: Sampling,
+    // Fight shadow acne by offsetting the ray origin along the normal instead of a t_min clamp
+    pub normal_offset: bool,
+    // Exponent applied to the cos^4 lens falloff, 0 disables vignetting
+    pub vignette: f64,
+    pub mode: Mode,
+    pub normal_space: NormalSpace,
+    // Count the samples that hit geometry, for an alpha channel
+    pub alpha: bool,
+    // Shade metals as Lambertians of the same albedo for judging forms
+    pub matte_metals: bool,
+    // End paths at random once they carry little light, unbiased but noisier per sample
+    pub russian_roulette: bool,
+    // Leave lights to be found by chance bounces, to compare against next-event estimation
+    pub no_light_sampling: bool,
+    // Upper bound on light arriving from the second bounce on, to tame fireflies
+    pub indirect_clamp: Option<f64>,
+    // Report progress and passes on stderr, off for library use
+    pub progress: bool,
+    // Overrides the world's background when given
+    pub background: Option<Background>,
+    // Color of the lower hemisphere of the default sky
+    pub ground_color: Option<Color>,
+}
+
+impl Settings {
+    // A plain beauty render, with the same defaults as the command line
+    pub fn new(width: u64, height: u64, samples_per_pixel: u64) -> Settings {
+        Settings {
+            width,
+            height,
+            samples_per_pixel,
+            max_depth: 50,
+            seed: None,
+            sampling: Sampling::Random,
+            normal_offset: false,
+            vignette: 0.0,
+            mode: Mode::Beauty,
+            normal_space: NormalSpace::World,
+            alpha: false,
+            matte_metals: false,
+            russian_roulette: false,
+            no_light_sampling: false,
+            indirect_clamp: None,
+            progress: false,
+            background: None,
+            ground_color: None,
+        }
+    }
+
+    fn t_min(&self) -> f64 {
+        if self.normal_offset {
+            0.0
+        } else {
+            T_MIN
+        }
+    }
+}
+
+// A finished frame, row by row from the top
+pub struct Image {
+    pub width: u64,
+    pub height: u64,
+    // Every pixel holds the sums of this many samples
+    pub samples_per_pixel: u64,
+    pub pixels: Vec<Pixel>,
+}
+
+// The whole frame with settings.samples_per_pixel samples in every pixel
+pub fn render(world: &World, camera: &Camera, settings: &Settings) -> Image {
+    let renderer = Renderer::new(world, settings);
+    Image {
+        width: settings.width,
+        height: settings.height,
+        samples_per_pixel: settings.samples_per_pixel,
+        pixels: renderer.render_frame(camera, settings.samples_per_pixel, 0),
+    }
+}
+
+// What a path went through before reaching the current ray
+#[derive(Clone, Copy)]
+struct PathState {
+    // Scattered at least once, whatever the ray hits reaches the camera after a bounce
+    indirect: bool,
+    // Left a diffuse surface that already sampled the lights
+    light_sampled: bool,
+    splits_left: u32,
+    bounces: u32,
+    // Product of the attenuations so far, the share of light at the end that reaches the camera
+    throughput: Color,
+}
+
+impl PathState {
+    fn camera() -> PathState {
+        PathState {
+            indirect: false,
+            light_sampled: false,
+            splits_left: MAX_SPLITS,
+            bounces: 0,
+            throughput: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn bounce(self, light_sampled: bool, attenuation: Color) -> PathState {
+        PathState {
+            indirect: true,
+            light_sampled,
+            bounces: self.bounces + 1,
+            throughput: self.throughput * attenuation,
+            ..self
+        }
+    }
+}
+
+fn ray_color(
+    r: &Ray,
+    world: &World,
+    depth: u64,
+    path: PathState,
+    settings: &Settings,
+    rng: &mut dyn RngCore,
+) -> Color {
+    let (mut r, mut depth, mut path) = (*r, depth, path);
+    // Light picked up at each hit along the way and the attenuation of whatever comes after it
+    let mut vertices: Vec<(Color, Color, PathState)> = Vec::new();
+
+    let mut color = loop {
+        if depth == 0 {
+            break Color::new(0.0, 0.0, 0.0);
+        }
+
+        let mut rec = match world.hit(&r, settings.t_min(), T_MAX) {
+            Some(rec) => rec,
+            None => {
+                break match settings
+                    .background
+                    .as_ref()
+                    .unwrap_or_else(|| world.background())
+                {
+                    Background::SolidColor(color) => *color,
+                    Background::Gradient => sky(&r, settings),
+                    Background::EnvironmentMap(map) => {
+                        let (u, v) = equirect_uv(r.direction());
+                        map.value(u, v, &r.origin())
+                    }
+                }
+            }
+        };
+        rec.normal = rec.mat.shading_normal(&rec);
+        if rec.mat.catches_shadows() {
+            let unshadowed = unshadowed(&rec, r.time(), world, settings, rng);
+            let attenuation = Color::new(unshadowed, unshadowed, unshadowed);
+            vertices.push((Color::new(0.0, 0.0, 0.0), attenuation, path));
+            r = bounce(Ray::new(rec.p, r.direction(), r.time()), &rec, settings);
+            depth -= 1;
+            continue;
+        }
+        // Counted by the light sampling at the previous hit already
+        let emitted = if path.light_sampled {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            rec.mat.emitted()
+        };
+        let split = match rec.mat.split(&r, &rec) {
+            Some(children) if path.splits_left > 0 => Some(children),
+            _ => None,
+        };
+        if let Some(children) = split {
+            // The only recursion left, MAX_SPLITS deep at most
+            let color = children
+                .into_iter()
+                .fold(emitted, |color, (weight, scattered)| {
+                    let scattered = bounce(scattered, &rec, settings);
+                    stats::record(|stats| stats.scatter_rays += 1);
+                    let child_path = PathState {
+                        splits_left: path.splits_left - 1,
+                        ..path.bounce(false, weight)
+                    };
+                    color
+                        + weight
+                            * ray_color(&scattered, world, depth - 1, child_path, settings, rng)
+                });
+            break clamp_indirect(color, path, settings);
+        }
+
+        match scatter(&r, &rec, settings, rng) {
+            Some((attenuation, scattered)) => {
+                let (direct, sampled) = match diffuse_albedo(&rec, settings) {
+                    Some(albedo) if world.has_lights() && !settings.no_light_sampling => (
+                        albedo * sample_lights(&rec, r.time(), world, settings, rng).0,
+                        true,
+                    ),
+                    _ => (Color::new(0.0, 0.0, 0.0), false),
+                };
+
+                // Past a few bounces, go on only as often as the path still carries light,
+                // and weigh the survivors up to make up for the paths cut short
+                let survival = match settings.russian_roulette {
+                    true if path.bounces >= ROULETTE_MIN_BOUNCES => {
+                        (path.throughput * attenuation).max_component().min(1.0)
+                    }
+                    _ => 1.0,
+                };
+                if survival < 1.0 && rng.gen::<f64>() >= survival {
+                    break clamp_indirect(emitted + direct, path, settings);
+                }
+                let attenuation = attenuation / survival;
+
+                vertices.push((emitted + direct, attenuation, path));
+                stats::record(|stats| stats.scatter_rays += 1);
+                r = scattered;
+                depth -= 1;
+                path = path.bounce(sampled, attenuation);
+            }
+            None => break clamp_indirect(emitted, path, settings),
+        }
+    };
+
+    // Fold back from the end of the path, the clamp does not distribute over the sum
+    for (radiance, attenuation, path) in vertices.into_iter().rev() {
+        color = clamp_indirect(radiance + attenuation * color, path, settings);
+    }
+    color
+}
+
+fn clamp_indirect(color: Color, path: PathState, settings: &Settings) -> Color {
+    match settings.indirect_clamp {
+        // Scale down rather than clip channels, to keep the hue of the path
+        Some(limit) if path.indirect && color.max_component() > limit => {
+            color * (limit / color.max_component())
+        }
+        _ => color,
+    }
+}
+
+fn sky(r: &Ray, settings: &Settings) -> Color {
+    let unit_direction = r.direction().normalized();
+    let t = 0.5 * (unit_direction.y() + 1.0);
+    let sky = (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
+
+    match settings.ground_color {
+        Some(ground) => {
+            let s = (0.5 + 0.5 * unit_direction.y() / HORIZON_BLEND).clamp(0.0, 1.0);
+            (1.0 - s) * ground + s * sky
+        }
+        None => sky,
+    }
+}
+
+fn diffuse_albedo(rec: &HitRecord, settings: &Settings) -> Option<Color> {
+    match rec.mat.matte_albedo() {
+        Some(albedo) if settings.matte_metals => Some(albedo),
+        _ => rec.mat.diffuse_albedo(rec),
+    }
+}
+
+// Next-event estimation for a diffuse hit: one shadow ray towards each light, weighted
+// to match the cosine-distributed bounce, so the result is scaled by the albedo only.
+// Also gives the irradiance there would be if nothing stood in the way.
+fn sample_lights(
+    rec: &HitRecord,
+    time: f64,
+    world: &World,
+    settings: &Settings,
+    rng: &mut dyn RngCore,
+) -> (Color, Color) {
+    let mut irradiance = Color::new(0.0, 0.0, 0.0);
+    let mut unblocked = Color::new(0.0, 0.0, 0.0);
+    for light in world.lights() {
+        let direction = light.random(rec.p, rng);
+        let cos_theta = direction.dot(rec.normal);
+        let pdf = light.pdf_value(rec.p, direction);
+        if cos_theta <= 0.0 || pdf <= 0.0 {
+            continue;
+        }
+
+        stats::record(|stats| stats.shadow_rays += 1);
+        let mut shadow = Ray::new(rec.p, direction, time);
+        if settings.normal_offset {
+            shadow = offset_origin(shadow, rec);
+        }
+        let light_rec = match light.hit(&shadow, settings.t_min(), T_MAX) {
+            Some(light_rec) => light_rec,
+            None => continue,
+        };
+        // Stop just short of the light so its own surface does not occlude it
+        let occluded = world
+            .hit(&shadow, settings.t_min(), light_rec.t * (1.0 - 1.0e-6))
+            .is_some();
+        let arriving = light_rec.mat.emitted() * (cos_theta / (std::f64::consts::PI * pdf));
+        unblocked += arriving;
+        if !occluded {
+            irradiance += arriving;
+        }
+    }
+    (irradiance, unblocked)
+}
+
+// Share of the light at a shadow catcher's hit that the scene does not block. Without lights
+// the background lights the scene, and a cosine-distributed ray tells if it is seen.
+fn unshadowed(
+    rec: &HitRecord,
+    time: f64,
+    world: &World,
+    settings: &Settings,
+    rng: &mut dyn RngCore,
+) -> f64 {
+    // Only the outside catches shadows, so rays leave a closed catcher freely
+    if !rec.front_face {
+        return 1.0;
+    }
+    if world.has_lights() {
+        let (irradiance, unblocked) = sample_lights(rec, time, world, settings, rng);
+        return if unblocked.luminance() > 0.0 {
+            irradiance.luminance() / unblocked.luminance()
+        } else {
+            1.0
+        };
+    }
+
+    let mut direction = rec.normal + Vec3::random_unit_vector(rng);
+    if direction.near_zero() {
+        direction = rec.normal;
+    }
+    stats::record(|stats| stats.shadow_rays += 1);
+    let mut shadow = Ray::new(rec.p, direction, time);
+    if settings.normal_offset {
+        shadow = offset_origin(shadow, rec);
+    }
+    match world.hit(&shadow, settings.t_min(), T_MAX) {
+        Some(_) => 0.0,
+        None => 1.0,
+    }
+}
+
+fn scatter(
+    r_in: &Ray,
+    rec: &HitRecord,
+    settings: &Settings,
+    rng: &mut dyn RngCore,
+) -> Option<(Color, Ray)> {
+    let (attenuation, scattered) = match rec.mat.matte_albedo() {
+        Some(albedo) if settings.matte_metals => Some((albedo, diffuse_bounce(r_in, rec, rng))),
+        _ => rec.mat.scatter(r_in, rec, rng),
+    }?;
+
+    Some((attenuation, bounce(scattered, rec, settings)))
+}
+
+// Materials may return any length, e.g. Lambertian's normal plus a unit vector. Bounced
+// rays leave here unit length, so error cannot build up over a long chain.
+fn bounce(scattered: Ray, rec: &HitRecord, settings: &Settings) -> Ray {
+    let scattered = Ray::new(
+        scattered.origin(),
+        scattered.direction().normalized(),
+        scattered.time(),
+    );
+
+    if settings.normal_offset {
+        offset_origin(scattered, rec)
+    } else {
+        scattered
+    }
+}
+
+// Offset towards the side the ray leaves on, so refracted rays go inside
+fn offset_origin(scattered: Ray, rec: &HitRecord) -> Ray {
+    let normal = if scattered.direction().dot(rec.normal) < 0.0 {
+        -rec.normal
+    } else {
+        rec.normal
+    };
+    Ray::new(
+        rec.p + NORMAL_OFFSET * normal,
+        scattered.direction(),
+        scattered.time(),
+    )
+}
+
+fn ray_bounces(
+    r: &Ray,
+    world: &World,
+    max_depth: u64,
+    settings: &Settings,
+    rng: &mut dyn RngCore,
+) -> u64 {
+    let mut ray = *r;
+    let mut bounces = 0;
+
+    while bounces < max_depth {
+        let rec = match world.hit(&ray, settings.t_min(), T_MAX) {
+            Some(rec) => rec,
+            None => break,
+        };
+        match scatter(&ray, &rec, settings, rng) {
+            Some((_, scattered)) => ray = scattered,
+            None => break,
+        }
+        bounces += 1;
+    }
+    bounces
+}
+
+// Blue through green to red as t goes from 0 to 1
+pub fn heatmap(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+}
+
+// Diffuse paths lose most of their energy within a few bounces, while mirrors
+// and especially glass keep them alive, so give those scenes more depth
+pub fn auto_max_depth(world: &World) -> u64 {
+    let (specular, dielectric) = specular_shares(world);
+    8 + (42.0 * (3.0 * specular + 20.0 * dielectric).min(1.0)).round() as u64
+}
+
+// Fractions of the objects with specular and with dielectric materials
+pub fn specular_shares(world: &World) -> (f64, f64) {
+    let kinds: Vec<MaterialKind> = world
+        .iter()
+        .filter_map(|object| object.material())
+        .map(|mat| mat.kind())
+        .collect();
+    let share = |kind: MaterialKind| {
+        kinds.iter().filter(|k| **k == kind).count() as f64 / kinds.len().max(1) as f64
+    };
+    (
+        share(MaterialKind::Specular),
+        share(MaterialKind::Dielectric),
+    )
+}
+
+// Independent generator for each stream, derived from the seed when there is one
+pub fn stream_rng(seed: Option<u64>, stream: u64) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+        None => StdRng::from_rng(thread_rng()).expect("thread_rng does not fail"),
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Pixel {
+    // Sum of all samples
+    pub color: Color,
+    // Fraction of samples that hit geometry
+    pub coverage: f64,
+    // Sum of the squared luminance of all samples
+    pub luminance_sq: f64,
+}
+
+impl Pixel {
+    // Unbiased variance of a single sample's luminance, not of the pixel mean, so
+    // it does not depend on the sample count. Divide by `samples` for the latter.
+    pub fn variance(&self, samples: u64) -> f64 {
+        let n = samples as f64;
+        let mean = self.color.luminance() / n;
+        (self.luminance_sq / n - mean * mean).max(0.0) * n / (n - 1.0).max(1.0)
+    }
+}
+
+// Whether the mean of `samples` summed samples is known well enough. The standard error of
+// the luminance goes through the output's gamma 2 first, d(sqrt L) = dL / (2 sqrt L), so
+// dark pixels, where noise shows the most, need a smaller one.
+fn converged(pixel: &Pixel, samples: u64, tolerance: f64) -> bool {
+    let n = samples as f64;
+    let mean = (pixel.color.luminance() / n).max(0.0);
+    let std_error = (pixel.variance(samples) / n).sqrt();
+    std_error <= 2.0 * tolerance * mean.sqrt()
+}
+
+// Renders frames of one world with one set of settings, in the ways the command line offers
+pub struct Renderer<'a> {
+    world: &'a World,
+    settings: &'a Settings,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(world: &'a World, settings: &'a Settings) -> Renderer<'a> {
+        Renderer { world, settings }
+    }
+
+    // Each pass over a pixel or corner gets its own stream, so seeded output does not
+    // depend on the thread count or the order pixels are rendered in
+    fn rng(&self, pass: u64, idx: u64) -> StdRng {
+        stream_rng(self.settings.seed, 1 + (pass << 40) + idx)
+    }
+
+    fn render_pixel(&self, cam: &Camera, i: u64, j: u64, samples: u64, pass: u64) -> Pixel {
+        let (world, settings) = (self.world, self.settings);
+        let max_depth = settings.max_depth;
+        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+        let mut luminance_sq = 0.0;
+        let mut coverage = 0.0;
+        let mut taken = 0;
+        let mut rng = self.rng(pass, j * self.settings.width + i);
+        // Cells per side, the largest grid that every cell gets a sample in. Samples past
+        // strata^2 go round the grid again, and a 1x1 grid is plain random sampling.
+        let strata = match settings.sampling {
+            Sampling::Random => 1,
+            Sampling::Stratified => ((samples as f64).sqrt() as u64).max(1),
+        };
+        while taken < samples {
+            let cell = taken % (strata * strata);
+            let random_u = ((cell % strata) as f64 + rng.gen::<f64>()) / strata as f64;
+            let random_v = ((cell / strata) as f64 + rng.gen::<f64>()) / strata as f64;
+
+            let u = ((i as f64) + random_u) / ((self.settings.width - 1) as f64);
+            let v = ((j as f64) + random_v) / ((self.settings.height - 1) as f64);
+
+            let r = cam.get_ray(u, v, &mut rng);
+            stats::record(|stats| stats.primary_rays += 1);
+            // A shadow catcher in a beauty render covers the pixel as far as its shadow does
+            let mut catcher = None;
+            if settings.alpha {
+                match world.hit(&r, settings.t_min(), T_MAX) {
+                    Some(rec) if rec.mat.catches_shadows() && settings.mode == Mode::Beauty => {
+                        catcher = Some(rec)
+                    }
+                    Some(_) => coverage += 1.0,
+                    None => (),
+                }
+            }
+            let mut sample = match settings.mode {
+                Mode::Beauty => match catcher {
+                    Some(mut rec) => {
+                        rec.normal = rec.mat.shading_normal(&rec);
+                        coverage += 1.0 - unshadowed(&rec, r.time(), world, settings, &mut rng);
+                        Color::new(0.0, 0.0, 0.0)
+                    }
+                    None => ray_color(
+                        &r,
+                        world,
+                        max_depth,
+                        PathState::camera(),
+                        settings,
+                        &mut rng,
+                    ),
+                },
+                Mode::Bounces => {
+                    let bounces = ray_bounces(&r, world, max_depth, settings, &mut rng);
+                    heatmap((bounces as f64 / max_depth as f64).sqrt())
+                }
+                Mode::Coc => match world.hit(&r, settings.t_min(), T_MAX) {
+                    Some(rec) => {
+                        let defocus = cam.defocus(rec.p).min(1.0);
+                        Color::new(defocus, defocus, defocus)
+                    }
+                    None => Color::new(1.0, 1.0, 1.0),
+                },
+                Mode::Normals => match world.hit(&r, settings.t_min(), T_MAX) {
+                    Some(rec) => {
+                        let normal = rec.mat.shading_normal(&rec);
+                        let normal = match settings.normal_space {
+                            NormalSpace::World => normal,
+                            NormalSpace::Camera => cam.to_camera_space(normal),
+                        };
+                        // Squared, so the output gamma leaves the encoding linear
+                        let encoded = 0.5 * (normal + Vec3::new(1.0, 1.0, 1.0));
+                        encoded * encoded
+                    }
+                    None => Color::new(0.0, 0.0, 0.0),
+                },
+                // Golden ratio steps around the heatmap keep neighbouring indices apart
+                Mode::Aabb => match world.first_box(&r, settings.t_min(), T_MAX) {
+                    Some((idx, _)) => heatmap((idx as f64 * 0.618_034).fract()),
+                    None => Color::new(0.0, 0.0, 0.0),
+                },
+            };
+            if settings.vignette > 0.0 {
+                sample *= cam.falloff(&r).powf(settings.vignette);
+            }
+            pixel_color += sample;
+            luminance_sq += sample.luminance().powi(2);
+            taken += 1;
+
+            // After Ctrl-C every pixel still gets one sample, so a partial image has no holes
+            if interrupt::requested() {
+                break;
+            }
+        }
+
+        stats::flush();
+        // Sums always stand for `samples`, however many were taken
+        let scale = samples as f64 / taken as f64;
+        Pixel {
+            color: pixel_color * scale,
+            coverage: coverage / taken as f64,
+            luminance_sq: luminance_sq * scale,
+        }
+    }
+
+    // For --stream: blocks of a few rows per thread are rendered in parallel and handed out
+    // top to bottom before the next block starts, so memory stays bounded by the block size
+    // whatever the frame size. The row in a block that finishes last holds the others back.
+    pub fn render<F>(&self, cam: &Camera, mut on_scanline: F) -> io::Result<()>
+    where
+        F: FnMut(Vec<Pixel>) -> io::Result<()>,
+    {
+        let height = self.settings.height as usize;
+        let block = STREAM_ROWS_PER_THREAD * rayon::current_num_threads();
+        let progress = Progress::new(height, self.settings.progress);
+        for first in (0..height).step_by(block) {
+            let scanlines: Vec<Vec<Pixel>> = (first..height.min(first + block))
+                .into_par_iter()
+                .map(|row| {
+                    let j = (height - 1 - row) as u64;
+                    let scanline = (0..self.settings.width)
+                        .map(|i| self.render_pixel(cam, i, j, self.settings.samples_per_pixel, 0))
+                        .collect();
+                    progress.inc();
+                    scanline
+                })
+                .collect();
+            for scanline in scanlines {
+                on_scanline(scanline)?;
+            }
+        }
+        progress.finish();
+        Ok(())
+    }
+
+    // The whole frame as one parallel job, top row first. There is no sync point per row,
+    // so threads do not sit idle waiting for the slowest pixel of a scanline.
+    pub fn render_frame(&self, cam: &Camera, samples: u64, pass: u64) -> Vec<Pixel> {
+        let (width, height) = (self.settings.width as usize, self.settings.height as usize);
+        let progress = Progress::new(width * height, self.settings.progress);
+        let pixels = (0..width * height)
+            .into_par_iter()
+            .map(|idx| {
+                let (i, j) = ((idx % width) as u64, (height - 1 - idx / width) as u64);
+                let pixel = self.render_pixel(cam, i, j, samples, pass);
+                progress.inc();
+                pixel
+            })
+            .collect();
+        progress.finish();
+        pixels
+    }
+
+    // In passes of the checkpoint's pass size until there are samples_per_pixel, saving the
+    // sums to `path` after each. Passes are numbered across runs, so a resumed render adds up
+    // the very same samples as one that was never stopped.
+    pub fn render_checkpointed(
+        &self,
+        cam: &Camera,
+        mut checkpoint: Checkpoint,
+        path: &str,
+    ) -> io::Result<Vec<Pixel>> {
+        while checkpoint.samples < self.settings.samples_per_pixel {
+            let samples = checkpoint
+                .pass_samples
+                .min(self.settings.samples_per_pixel - checkpoint.samples);
+            if self.settings.progress {
+                eprintln!(
+                    "Pass {}: samples {} to {}",
+                    checkpoint.passes + 1,
+                    checkpoint.samples + 1,
+                    checkpoint.samples + samples
+                );
+            }
+            let pass = self.render_frame(cam, samples, checkpoint.passes);
+            if interrupt::requested() {
+                if checkpoint.samples == 0 {
+                    eprintln!("Interrupted before the first checkpoint, the remaining pixels got a single sample");
+                    return Ok(pass);
+                }
+                // A pass cut short is dropped, the image and the checkpoint keep whole passes
+                eprintln!(
+                    "Interrupted, {} has {} samples per pixel to --resume from",
+                    path, checkpoint.samples
+                );
+                break;
+            }
+            for (sum, pixel) in checkpoint.sums.iter_mut().zip(&pass) {
+                let coverage = pixel.coverage * samples as f64;
+                let color = pixel.color;
+                *sum = [
+                    sum[0] + color[0],
+                    sum[1] + color[1],
+                    sum[2] + color[2],
+                    sum[3] + coverage,
+                    sum[4] + pixel.luminance_sq,
+                ];
+            }
+            checkpoint.passes += 1;
+            checkpoint.samples += samples;
+            checkpoint.save(path)?;
+        }
+
+        // Rescaled to samples_per_pixel for the writers, a checkpoint may hold more or fewer
+        let scale = self.settings.samples_per_pixel as f64 / checkpoint.samples as f64;
+        Ok(checkpoint
+            .sums
+            .iter()
+            .map(|sum| Pixel {
+                color: Color::new(sum[0], sum[1], sum[2]) * scale,
+                coverage: sum[3] / checkpoint.samples as f64,
+                luminance_sq: sum[4] * scale,
+            })
+            .collect())
+    }
+
+    // Every pixel in batches of `batch` samples, up to samples_per_pixel but stopping early
+    // once it has converged to within `tolerance`. Batches are passes of their own, so the
+    // result does not depend on when neighbours stop. Also returns the samples each pixel took.
+    pub fn render_adaptive(
+        &self,
+        cam: &Camera,
+        tolerance: f64,
+        batch: u64,
+    ) -> (Vec<Pixel>, Vec<u64>) {
+        let (width, height) = (self.settings.width as usize, self.settings.height as usize);
+        let progress = Progress::new(width * height, self.settings.progress);
+        let (pixels, counts) = (0..width * height)
+            .into_par_iter()
+            .map(|idx| {
+                let (i, j) = ((idx % width) as u64, (height - 1 - idx / width) as u64);
+                let mut sum = Pixel {
+                    color: Color::new(0.0, 0.0, 0.0),
+                    coverage: 0.0,
+                    luminance_sq: 0.0,
+                };
+                let mut taken = 0;
+                let mut pass = 0;
+                loop {
+                    let samples = batch.min(self.settings.samples_per_pixel - taken);
+                    let more = self.render_pixel(cam, i, j, samples, pass);
+                    // Coverage is summed as a hit count here and made a fraction at the end
+                    sum = Pixel {
+                        color: sum.color + more.color,
+                        coverage: sum.coverage + more.coverage * samples as f64,
+                        luminance_sq: sum.luminance_sq + more.luminance_sq,
+                    };
+                    taken += samples;
+                    pass += 1;
+                    if taken == self.settings.samples_per_pixel
+                        || interrupt::requested()
+                        || converged(&sum, taken, tolerance)
+                    {
+                        break;
+                    }
+                }
+                progress.inc();
+
+                // Rescaled to samples_per_pixel for the writers, like edge AA does
+                let scale = self.settings.samples_per_pixel as f64 / taken as f64;
+                let pixel = Pixel {
+                    color: sum.color * scale,
+                    coverage: sum.coverage / taken as f64,
+                    luminance_sq: sum.luminance_sq * scale,
+                };
+                (pixel, taken)
+            })
+            .unzip();
+        progress.finish();
+        (pixels, counts)
+    }
+
+    // Render everything with `base_samples`, then bring only edge pixels up to the full
+    // sample count. Sums are rescaled to samples_per_pixel so the writers stay unaware.
+    pub fn render_edge_aa(
+        &self,
+        cam: &Camera,
+        base_samples: u64,
+        detect: EdgeDetect,
+    ) -> Vec<Pixel> {
+        let (width, height) = (self.settings.width as usize, self.settings.height as usize);
+        let scale = self.settings.samples_per_pixel as f64 / base_samples as f64;
+
+        if self.settings.progress {
+            eprintln!("Edge AA: base pass with {} samples", base_samples);
+        }
+        let base = self.render_frame(cam, base_samples, 0);
+
+        let edges = match detect {
+            EdgeDetect::Luminance => self.luminance_edges(&base, base_samples),
+            EdgeDetect::Coverage => self.mixed_pixels(cam),
+        };
+        let extra_samples = self.settings.samples_per_pixel.saturating_sub(base_samples);
+        if self.settings.progress {
+            eprintln!(
+                "Edge AA: refining {} of {} pixels with {} more samples",
+                edges.len(),
+                width * height,
+                extra_samples
+            );
+        }
+
+        let mut pixels: Vec<Pixel> = base
+            .iter()
+            .map(|pixel| Pixel {
+                color: pixel.color * scale,
+                luminance_sq: pixel.luminance_sq * scale,
+                ..*pixel
+            })
+            .collect();
+        if extra_samples == 0 {
+            return pixels;
+        }
+        let progress = Progress::new(edges.len(), self.settings.progress);
+        let refined: Vec<Pixel> = edges
+            .par_iter()
+            .map(|idx| {
+                let (i, j) = ((idx % width) as u64, (height - 1 - idx / width) as u64);
+                let pixel = self.render_pixel(cam, i, j, extra_samples, 1);
+                progress.inc();
+                pixel
+            })
+            .collect();
+        progress.finish();
+        for (idx, extra) in edges.into_iter().zip(refined) {
+            let base = base[idx];
+            let coverage = (base.coverage * base_samples as f64
+                + extra.coverage * extra_samples as f64)
+                / self.settings.samples_per_pixel as f64;
+            pixels[idx] = Pixel {
+                color: base.color + extra.color,
+                coverage,
+                luminance_sq: base.luminance_sq + extra.luminance_sq,
+            };
+        }
+        pixels
+    }
+
+    // Indices of pixels whose luminance differs a lot from a neighbour's
+    fn luminance_edges(&self, base: &[Pixel], base_samples: u64) -> Vec<usize> {
+        let (width, height) = (self.settings.width as usize, self.settings.height as usize);
+
+        // Compare display-referred luminance, gamma 2 like the output
+        let luminance: Vec<f64> = base
+            .iter()
+            .map(|pixel| (pixel.color.luminance() / base_samples as f64).sqrt())
+            .collect();
+        let is_edge = |idx: usize| {
+            let (col, row) = (idx % width, idx / width);
+            let mut neighbours = Vec::with_capacity(4);
+            if col > 0 {
+                neighbours.push(idx - 1);
+            }
+            if col + 1 < width {
+                neighbours.push(idx + 1);
+            }
+            if row > 0 {
+                neighbours.push(idx - width);
+            }
+            if row + 1 < height {
+                neighbours.push(idx + width);
+            }
+            neighbours
+                .iter()
+                .any(|n| (luminance[*n] - luminance[idx]).abs() > EDGE_THRESHOLD)
+        };
+        (0..width * height).filter(|idx| is_edge(*idx)).collect()
+    }
+
+    // Indices of pixels whose four corners do not all see the same object first. Corners
+    // are shared between neighbours, so this costs one primary ray per pixel.
+    fn mixed_pixels(&self, cam: &Camera) -> Vec<usize> {
+        let (width, height) = (self.settings.width as usize, self.settings.height as usize);
+        let (world, settings) = (self.world, self.settings);
+
+        // Grid of (width + 1) x (height + 1) corners, bottom row first
+        let corners: Vec<Option<usize>> = (0..(width + 1) * (height + 1))
+            .into_par_iter()
+            .map(|idx| {
+                let (ci, cj) = (idx % (width + 1), idx / (width + 1));
+                let u = ci as f64 / (width - 1) as f64;
+                let v = cj as f64 / (height - 1) as f64;
+                let r = cam.get_ray(u, v, &mut self.rng(2, idx as u64));
+                stats::record(|stats| stats.primary_rays += 1);
+                let first = world.hit_index(&r, settings.t_min(), T_MAX);
+                stats::flush();
+                first
+            })
+            .collect();
+
+        (0..width * height)
+            .filter(|idx| {
+                let (i, j) = (idx % width, height - 1 - idx / width);
+                let corner = |ci: usize, cj: usize| corners[cj * (width + 1) + ci];
+                let first = corner(i, j);
+                first != corner(i + 1, j)
+                    || first != corner(i, j + 1)
+                    || first != corner(i + 1, j + 1)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hit::WorldBuilder;
+    use crate::material::{DiffuseLight, Lambertian, ShadowCatcher};
+    use crate::rect::YzRect;
+    use crate::vec::Point3;
+
+    fn two_spheres() -> World {
+        WorldBuilder::new()
+            .sphere(
+                Point3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            )
+            .sphere(
+                Point3::new(0.0, 0.0, -1.0),
+                0.5,
+                Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3))),
+            )
+            .build()
+    }
+
+    fn bits(pixels: &[Pixel]) -> Vec<[u64; 3]> {
+        pixels
+            .iter()
+            .map(|pixel| {
+                let color = pixel.color;
+                [
+                    color.x().to_bits(),
+                    color.y().to_bits(),
+                    color.z().to_bits(),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streamed_rows_match_the_whole_frame() {
+        let world = two_spheres();
+        let cam = CameraBuilder::new().aspect_ratio(0.25).build();
+        let settings = Settings {
+            seed: Some(7),
+            ..Settings::new(8, 32, 4)
+        };
+
+        let frame = render(&world, &cam, &settings);
+        let mut streamed = Vec::new();
+        Renderer::new(&world, &settings)
+            .render(&cam, |scanline| {
+                streamed.extend(scanline);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(bits(&streamed), bits(&frame.pixels));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let world = two_spheres();
+        let cam = CameraBuilder::new().aspect_ratio(1.5).build();
+        let settings = Settings {
+            seed: Some(3),
+            ..Settings::new(24, 16, 4)
+        };
+        let on_threads = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut streamed = Vec::new();
+                Renderer::new(&world, &settings)
+                    .render(&cam, |scanline| {
+                        streamed.extend(scanline);
+                        Ok(())
+                    })
+                    .unwrap();
+                (
+                    bits(&render(&world, &cam, &settings).pixels),
+                    bits(&streamed),
+                )
+            })
+        };
+
+        assert_eq!(on_threads(1), on_threads(4));
+    }
+
+    #[test]
+    fn bounces_do_not_find_their_own_surface_again() {
+        let sphere = crate::sphere::Sphere::new(
+            Point3::new(0.3, -0.2, -4.0),
+            1.7,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let offset = Settings {
+            normal_offset: true,
+            ..Settings::new(1, 1, 1)
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut bare, mut epsilon, mut pushed) = (0, 0, 0);
+        for _ in 0..10_000 {
+            let aim = Point3::new(0.3, -0.2, -4.0) + 1.5 * Vec3::random_in_unit_sphere(&mut rng);
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), aim, 0.0);
+            let rec = sphere.hit(&r, T_MIN, T_MAX).unwrap();
+            let leaving = Ray::new(rec.p, Vec3::random_in_hemisphere(rec.normal, &mut rng), 0.0);
+
+            bare += sphere.hit(&leaving, 0.0, T_MAX).is_some() as u32;
+            epsilon += sphere.hit(&leaving, T_MIN, T_MAX).is_some() as u32;
+            let leaving = bounce(leaving, &rec, &offset);
+            pushed += sphere.hit(&leaving, offset.t_min(), T_MAX).is_some() as u32;
+        }
+        // Without either guard rounding puts a fair share of origins just below the surface
+        assert!(bare > 0);
+        assert_eq!((epsilon, pushed), (0, 0));
+    }
+
+    #[test]
+    fn shadow_catcher_shows_the_background_where_nothing_shadows_it() {
+        let background = Color::new(0.2, 0.4, 0.6);
+        let world = WorldBuilder::new()
+            .background(Background::SolidColor(background))
+            .sphere(
+                Point3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(ShadowCatcher),
+            )
+            .build();
+        let cam = CameraBuilder::new().build();
+        let settings = Settings {
+            seed: Some(5),
+            ..Settings::new(6, 6, 4)
+        };
+
+        for pixel in render(&world, &cam, &settings).pixels {
+            assert!((pixel.color / 4.0 - background).length() < 1e-12);
+        }
+        let alpha = Settings {
+            alpha: true,
+            ..settings
+        };
+        let pixels = render(&world, &cam, &alpha).pixels;
+        assert!(pixels.iter().all(|pixel| pixel.coverage == 0.0));
+        // The bottom row looks at the catcher, which is black under its alpha
+        assert_eq!(pixels[30].color.length(), 0.0);
+    }
+
+    #[test]
+    fn shadow_catcher_is_shadowed_under_an_occluder() {
+        let world = WorldBuilder::new()
+            .sphere(
+                Point3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(ShadowCatcher),
+            )
+            .sphere(
+                Point3::new(0.0, 0.0, -1.0),
+                0.5,
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            )
+            .light(crate::sphere::Sphere::new(
+                Point3::new(0.0, 3.0, -1.0),
+                0.3,
+                Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+            ))
+            .build();
+        let settings = Settings::new(1, 1, 1);
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut unshadowed_at = |x: f64| {
+            let down = Ray::new(Point3::new(x, -0.45, -1.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+            let rec = world.hit(&down, T_MIN, T_MAX).unwrap();
+            assert!(rec.mat.catches_shadows());
+            unshadowed(&rec, 0.0, &world, &settings, &mut rng)
+        };
+
+        assert_eq!(unshadowed_at(0.0), 0.0);
+        assert_eq!(unshadowed_at(3.0), 1.0);
+    }
+
+    #[test]
+    fn glass_gets_more_depth_than_diffuse() {
+        assert_eq!(specular_shares(&two_spheres()), (0.0, 0.0));
+        assert_eq!(auto_max_depth(&two_spheres()), 8);
+
+        let builder = WorldBuilder::new();
+        let glass = builder.glass(1.5);
+        let world = builder
+            .sphere(Point3::new(0.0, 0.0, -1.0), 0.5, glass)
+            .sphere(
+                Point3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            )
+            .build();
+        assert_eq!(specular_shares(&world), (0.0, 0.5));
+        assert_eq!(auto_max_depth(&world), 50);
+    }
+
+    #[test]
+    fn camera_space_normals_are_relative_to_the_view() {
+        // A wall at x = 2 seen head on by a camera looking down +x
+        let world = WorldBuilder::new()
+            .object(YzRect::new(
+                -10.0,
+                10.0,
+                -10.0,
+                10.0,
+                2.0,
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            ))
+            .build();
+        let cam = CameraBuilder::new()
+            .lookat(Point3::new(1.0, 0.0, 0.0))
+            .build();
+        let encoded = |normal_space: NormalSpace| {
+            let settings = Settings {
+                mode: Mode::Normals,
+                normal_space,
+                seed: Some(1),
+                ..Settings::new(2, 2, 1)
+            };
+            render(&world, &cam, &settings).pixels
+        };
+
+        // The normal faces the camera, -x in the world and +w, back to the viewer, in camera
+        // space. Encoded as (n + 1) / 2 and squared to stay linear under gamma 2.
+        for (space, expected) in [
+            (NormalSpace::World, Color::new(0.0, 0.25, 0.25)),
+            (NormalSpace::Camera, Color::new(0.25, 0.25, 1.0)),
+        ] {
+            for pixel in encoded(space) {
+                assert!((pixel.color - expected).length() < 1e-12, "{}", pixel.color);
+            }
+        }
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean_brightness() {
+        // Dark surfaces, where most paths are ended early
+        let world = WorldBuilder::new()
+            .sphere(
+                Point3::new(0.0, -100.5, -1.0),
+                100.0,
+                Arc::new(Lambertian::new(Color::new(0.2, 0.2, 0.2))),
+            )
+            .sphere(
+                Point3::new(0.0, 0.0, -1.0),
+                0.5,
+                Arc::new(Lambertian::new(Color::new(0.3, 0.1, 0.1))),
+            )
+            .build();
+        // Looking down, so every camera ray lands on them
+        let cam = CameraBuilder::new()
+            .lookfrom(Point3::new(0.0, 1.0, 0.0))
+            .lookat(Point3::new(0.0, -0.5, -1.0))
+            .vfov(40.0)
+            .aspect_ratio(2.0)
+            .build();
+        let mean_luminance = |russian_roulette: bool| {
+            let settings = Settings {
+                seed: Some(5),
+                russian_roulette,
+                ..Settings::new(16, 8, 256)
+            };
+            let image = render(&world, &cam, &settings);
+            let total: f64 = image.pixels.iter().map(|p| p.color.luminance()).sum();
+            total / (image.pixels.len() * 256) as f64
+        };
+        let (full, roulette) = (mean_luminance(false), mean_luminance(true));
+        assert!(
+            (full - roulette).abs() < 0.02 * full,
+            "{} without and {} with russian roulette",
+            full,
+            roulette
+        );
+    }
+
+    #[test]
+    fn resumed_render_matches_one_never_stopped() {
+        let world = two_spheres();
+        let cam = CameraBuilder::new().aspect_ratio(1.5).build();
+        let settings = |samples: u64| Settings {
+            seed: Some(9),
+            ..Settings::new(12, 8, samples)
+        };
+        let temp = |name: &str| {
+            let path = std::env::temp_dir().join(format!(
+                "rayrust-{}-{}.checkpoint",
+                std::process::id(),
+                name
+            ));
+            path.to_str().unwrap().to_string()
+        };
+        let fresh = || Checkpoint::new(12, 8, Some(9), 0, 3);
+
+        // 10 samples in one go, in passes of 3
+        let whole = temp("whole");
+        let straight = Renderer::new(&world, &settings(10))
+            .render_checkpointed(&cam, fresh(), &whole)
+            .unwrap();
+
+        // Stopped after two passes, then resumed from the saved checkpoint
+        let stopped = temp("stopped");
+        Renderer::new(&world, &settings(6))
+            .render_checkpointed(&cam, fresh(), &stopped)
+            .unwrap();
+        let checkpoint = Checkpoint::load(&stopped).unwrap();
+        assert_eq!((checkpoint.passes, checkpoint.samples), (2, 6));
+        let resumed = Renderer::new(&world, &settings(10))
+            .render_checkpointed(&cam, checkpoint, &stopped)
+            .unwrap();
+        let saved = Checkpoint::load(&stopped).unwrap();
+        std::fs::remove_file(&whole).unwrap();
+        std::fs::remove_file(&stopped).unwrap();
+
+        assert_eq!((saved.passes, saved.samples), (4, 10));
+        assert_eq!(bits(&resumed), bits(&straight));
+    }
+}
@@ -0,0 +1,711 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::json::Json;
+use crate::material::{
+    self, DiffuseLight, Lambertian, Metal, Mirror, NormalMapped, Scatter, ShadowCatcher,
+};
+use crate::texture::ImageTexture;
+
+use super::hit::{Background, World, WorldBuilder};
+use super::sphere::Sphere;
+use super::vec::{Color, Point3, Vec3};
+
+// Where the camera sits and what it sees, the defaults frame the built-in scenes
+#[derive(Clone, Copy)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    // Vertical field of view in degrees
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            lookfrom: Point3::new(13.0, 2.0, 3.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.1,
+            focus_dist: 10.0,
+        }
+    }
+}
+
+enum MaterialDesc {
+    Lambertian {
+        albedo: Color,
+    },
+    Metal {
+        albedo: Color,
+        fuzz: f64,
+        fresnel: bool,
+        // Tangent, fuzz along it and fuzz across it
+        brush: Option<(Vec3, f64, f64)>,
+    },
+    Mirror {
+        albedo: Color,
+    },
+    Dielectric {
+        ir: f64,
+    },
+    // One of material::preset_names()
+    Preset {
+        name: String,
+    },
+    // Emitter, registered with the world's lights so diffuse surfaces sample it
+    DiffuseLight {
+        emit: Color,
+    },
+    ShadowCatcher,
+}
+
+// The background as written, an environment map is only read by `load`
+#[derive(Clone)]
+pub enum BackgroundDesc {
+    Gradient,
+    // Path of an equirectangular PPM
+    EnvironmentMap(String),
+    SolidColor(Color),
+}
+
+impl BackgroundDesc {
+    pub fn load(&self) -> Result<Background, String> {
+        match self {
+            BackgroundDesc::Gradient => Ok(Background::Gradient),
+            BackgroundDesc::EnvironmentMap(path) => Background::environment(path)
+                .map_err(|err| format!("cannot load environment map {:?}: {}", path, err)),
+            BackgroundDesc::SolidColor(color) => Ok(Background::SolidColor(*color)),
+        }
+    }
+}
+
+// "gradient", a color, as in "0,0,0", or the path of a .ppm environment map
+impl FromStr for BackgroundDesc {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BackgroundDesc, String> {
+        match s {
+            "gradient" => Ok(BackgroundDesc::Gradient),
+            _ if s.ends_with(".ppm") => Ok(BackgroundDesc::EnvironmentMap(s.to_string())),
+            _ => s.parse().map(BackgroundDesc::SolidColor),
+        }
+    }
+}
+
+impl MaterialDesc {
+    // Members of the material's object, read back by material_desc
+    fn members(&self) -> Vec<(String, Json)> {
+        let kind = |name: &str| member("type", name);
+        match self {
+            MaterialDesc::Lambertian { albedo } => {
+                vec![kind("lambertian"), member("albedo", *albedo)]
+            }
+            MaterialDesc::Metal {
+                albedo,
+                fuzz,
+                fresnel,
+                brush,
+            } => {
+                let mut members = vec![
+                    kind("metal"),
+                    member("albedo", *albedo),
+                    member("fuzz", *fuzz),
+                    member("fresnel", *fresnel),
+                ];
+                if let Some((tangent, along, across)) = brush {
+                    members.push(member("tangent", *tangent));
+                    members.push(member("tangent_fuzz", *along));
+                    members.push(member("bitangent_fuzz", *across));
+                }
+                members
+            }
+            MaterialDesc::Mirror { albedo } => vec![kind("mirror"), member("albedo", *albedo)],
+            MaterialDesc::Dielectric { ir } => vec![kind("dielectric"), member("ir", *ir)],
+            MaterialDesc::Preset { name } => vec![kind("preset"), member("name", name.as_str())],
+            MaterialDesc::DiffuseLight { emit } => {
+                vec![kind("diffuse_light"), member("emit", *emit)]
+            }
+            MaterialDesc::ShadowCatcher => vec![kind("shadow_catcher")],
+        }
+    }
+}
+
+struct SphereDesc {
+    center: Point3,
+    radius: f64,
+    material: MaterialDesc,
+    // Netpbm image bending the material's normal
+    normal_map: Option<String>,
+}
+
+// A scene read from a JSON file, for example:
+//
+//   {
+//     "cameras": [
+//       {"name": "wide", "lookfrom": [13, 2, 3], "lookat": [0, 0, 0], "vfov": 20},
+//       {"name": "close", "lookfrom": [4, 1, 1], "lookat": [0, 1, 0], "vfov": 30}
+//     ],
+//     "image": {"width": 400, "aspect": 1.5, "samples": 100},
+//     "background": "gradient",
+//     "objects": [
+//       {"type": "sphere", "center": [0, 1, 0], "radius": 1,
+//        "material": {"type": "metal", "albedo": [0.7, 0.6, 0.5], "fuzz": 0.1}}
+//     ]
+//   }
+//
+// Every section and camera field is optional. A single unnamed "camera" object may stand in
+// for the list, and is named "default" like the camera of a scene without either. Materials
+// are "lambertian" and "mirror" with an albedo, "metal" with an albedo, fuzz and optionally
+// "fresnel": true, and "dielectric" with an index of refraction "ir". A "preset" takes the
+// "name" of a metal or dielectric preset, such as "gold" or "diamond", "diffuse_light" makes
+// the sphere a light emitting "emit", and a "shadow_catcher" shows only the shadows on it. A
+// metal with a "tangent" is brushed along it, with "tangent_fuzz" and "bitangent_fuzz" in
+// place of fuzz. Any material takes a "normal_map", the path of a tangent-space normal map
+// in PPM. The background is "gradient", a color or the path of an
+// equirectangular PPM to light the scene with.
+pub struct SceneFile {
+    // Never empty, the first one is used unless another is picked by name
+    pub cameras: Vec<(String, CameraSettings)>,
+    // Image settings, the command line takes precedence
+    pub width: Option<u64>,
+    pub aspect: Option<f64>,
+    pub samples: Option<u64>,
+    // Left to the caller to load, so it can report a map that cannot be read
+    pub background: Option<BackgroundDesc>,
+    spheres: Vec<SphereDesc>,
+}
+
+impl SceneFile {
+    pub fn load(path: &str) -> Result<SceneFile, String> {
+        SceneFile::parse(&fs::read_to_string(path).map_err(|err| err.to_string())?)
+    }
+
+    pub fn parse(text: &str) -> Result<SceneFile, String> {
+        let json = Json::parse(text)?;
+
+        let cameras = match (json.get("camera"), json.get("cameras")) {
+            (Some(_), Some(_)) => return Err("give either camera or cameras, not both".to_string()),
+            (Some(cam), None) => vec![("default".to_string(), camera(cam, "camera")?)],
+            (None, Some(cams)) => {
+                let cams = cams.as_array().ok_or("cameras must be an array")?;
+                let mut cameras: Vec<(String, CameraSettings)> = Vec::new();
+                for (i, cam) in cams.iter().enumerate() {
+                    let context = format!("cameras[{}]", i);
+                    let name = cam
+                        .get("name")
+                        .and_then(Json::as_str)
+                        .filter(|name| !name.is_empty())
+                        .ok_or_else(|| format!("{}: missing name", context))?;
+                    if cameras.iter().any(|(other, _)| other == name) {
+                        return Err(format!("{}: another camera is named {:?}", context, name));
+                    }
+                    cameras.push((name.to_string(), camera(cam, &context)?));
+                }
+                if cameras.is_empty() {
+                    return Err("cameras must not be empty".to_string());
+                }
+                cameras
+            }
+            (None, None) => vec![("default".to_string(), CameraSettings::default())],
+        };
+
+        let (mut width, mut aspect, mut samples) = (None, None, None);
+        if let Some(image) = json.get("image") {
+            let context = "image";
+            width = count(image, "width", context)?;
+            aspect = number(image, "aspect", context)?;
+            samples = count(image, "samples", context)?;
+            if aspect.is_some_and(|aspect| !(aspect > 0.0 && aspect.is_finite())) {
+                return Err("image.aspect must be positive".to_string());
+            }
+        }
+
+        let background = match json.get("background") {
+            None => None,
+            Some(Json::String(s)) => Some(s.parse()?),
+            Some(value) => Some(BackgroundDesc::SolidColor(
+                Vec3::try_from(value)
+                    .map_err(|_| "background must be \"gradient\", a .ppm path or [r, g, b]")?,
+            )),
+        };
+
+        let objects = match json.get("objects") {
+            None => &[],
+            Some(objects) => objects.as_array().ok_or("objects must be an array")?,
+        };
+        let spheres = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| sphere(object, &format!("objects[{}]", i)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(SceneFile {
+            cameras,
+            width,
+            aspect,
+            samples,
+            background,
+            spheres,
+        })
+    }
+
+    pub fn build(&self, mut world: WorldBuilder) -> World {
+        for sphere in &self.spheres {
+            let mat: Arc<dyn Scatter> = match sphere.material {
+                MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::new(albedo)),
+                MaterialDesc::Metal {
+                    albedo,
+                    fuzz,
+                    fresnel,
+                    brush,
+                } => {
+                    let metal = Metal::new(albedo, fuzz).with_fresnel(fresnel);
+                    Arc::new(match brush {
+                        Some((tangent, along, across)) => metal.with_brush(tangent, along, across),
+                        None => metal,
+                    })
+                }
+                MaterialDesc::Mirror { albedo } => Arc::new(Mirror::new(albedo)),
+                MaterialDesc::Dielectric { ir } => world.glass(ir),
+                MaterialDesc::Preset { ref name } => {
+                    material::preset(name).expect("presets are checked when loading")
+                }
+                MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::new(emit)),
+                MaterialDesc::ShadowCatcher => Arc::new(ShadowCatcher),
+            };
+            let mat = match &sphere.normal_map {
+                Some(path) => Arc::new(NormalMapped::new(
+                    mat,
+                    Arc::new(ImageTexture::normal_map(path)),
+                )),
+                None => mat,
+            };
+            world = match sphere.material {
+                MaterialDesc::DiffuseLight { .. } => {
+                    world.light(Sphere::new(sphere.center, sphere.radius, mat))
+                }
+                _ => world.sphere(sphere.center, sphere.radius, mat),
+            };
+        }
+        world.build()
+    }
+
+    // The scene in the format `parse` reads, which gives back the same scene. Cameras are
+    // written as a named list with every field, defaults included.
+    pub fn to_json(&self) -> Json {
+        let cameras = self
+            .cameras
+            .iter()
+            .map(|(name, cam)| {
+                Json::Object(vec![
+                    member("name", name.as_str()),
+                    member("lookfrom", cam.lookfrom),
+                    member("lookat", cam.lookat),
+                    member("vup", cam.vup),
+                    member("vfov", cam.vfov),
+                    member("aperture", cam.aperture),
+                    member("focus_dist", cam.focus_dist),
+                ])
+            })
+            .collect();
+        let mut members = vec![member("cameras", Json::Array(cameras))];
+
+        let image: Vec<_> = [
+            ("width", self.width.map(|width| width as f64)),
+            ("aspect", self.aspect),
+            ("samples", self.samples.map(|samples| samples as f64)),
+        ]
+        .iter()
+        .filter_map(|&(key, value)| value.map(|value| member(key, value)))
+        .collect();
+        if !image.is_empty() {
+            members.push(member("image", Json::Object(image)));
+        }
+
+        if let Some(background) = &self.background {
+            members.push(member(
+                "background",
+                match background {
+                    BackgroundDesc::Gradient => Json::from("gradient"),
+                    BackgroundDesc::EnvironmentMap(path) => Json::from(path.as_str()),
+                    BackgroundDesc::SolidColor(color) => Json::from(*color),
+                },
+            ));
+        }
+
+        let objects = self
+            .spheres
+            .iter()
+            .map(|sphere| {
+                let mut material = sphere.material.members();
+                if let Some(path) = &sphere.normal_map {
+                    material.push(member("normal_map", path.as_str()));
+                }
+                Json::Object(vec![
+                    member("type", "sphere"),
+                    member("center", sphere.center),
+                    member("radius", sphere.radius),
+                    member("material", Json::Object(material)),
+                ])
+            })
+            .collect();
+        members.push(member("objects", Json::Array(objects)));
+        Json::Object(members)
+    }
+}
+
+// The camera of that name in a scene's list, or the first one
+pub fn named_camera(
+    cameras: &[(String, CameraSettings)],
+    name: Option<&str>,
+) -> Result<CameraSettings, String> {
+    let name = match name {
+        None => return Ok(cameras[0].1),
+        Some(name) => name,
+    };
+    match cameras.iter().find(|(camera, _)| camera == name) {
+        Some((_, settings)) => Ok(*settings),
+        None => {
+            let names: Vec<&str> = cameras.iter().map(|(name, _)| name.as_str()).collect();
+            Err(format!(
+                "no camera named {:?}, the scene has {}",
+                name,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+fn camera(cam: &Json, context: &str) -> Result<CameraSettings, String> {
+    let defaults = CameraSettings::default();
+    Ok(CameraSettings {
+        lookfrom: vec3(cam, "lookfrom", context)?.unwrap_or(defaults.lookfrom),
+        lookat: vec3(cam, "lookat", context)?.unwrap_or(defaults.lookat),
+        vup: vec3(cam, "vup", context)?.unwrap_or(defaults.vup),
+        vfov: number(cam, "vfov", context)?.unwrap_or(defaults.vfov),
+        aperture: number(cam, "aperture", context)?.unwrap_or(defaults.aperture),
+        focus_dist: number(cam, "focus_dist", context)?.unwrap_or(defaults.focus_dist),
+    })
+}
+
+fn sphere(object: &Json, context: &str) -> Result<SphereDesc, String> {
+    match object.get("type").and_then(Json::as_str) {
+        Some("sphere") => {}
+        Some(kind) => return Err(format!("{}: unknown object type {:?}", context, kind)),
+        None => return Err(format!("{}: missing type", context)),
+    }
+    let center =
+        vec3(object, "center", context)?.ok_or_else(|| format!("{}: missing center", context))?;
+    let radius =
+        number(object, "radius", context)?.ok_or_else(|| format!("{}: missing radius", context))?;
+    let material = object
+        .get("material")
+        .ok_or_else(|| format!("{}: missing material", context))?;
+    let normal_map = match material.get("normal_map") {
+        Some(path) => Some(
+            path.as_str()
+                .ok_or_else(|| format!("{}.material.normal_map must be a path", context))?
+                .to_string(),
+        ),
+        None => None,
+    };
+    Ok(SphereDesc {
+        center,
+        radius,
+        material: material_desc(material, &format!("{}.material", context))?,
+        normal_map,
+    })
+}
+
+fn material_desc(material: &Json, context: &str) -> Result<MaterialDesc, String> {
+    let albedo =
+        || vec3(material, "albedo", context)?.ok_or_else(|| format!("{}: missing albedo", context));
+    match material.get("type").and_then(Json::as_str) {
+        Some("lambertian") => Ok(MaterialDesc::Lambertian { albedo: albedo()? }),
+        Some("metal") => {
+            let fuzz = number(material, "fuzz", context)?.unwrap_or(0.0);
+            let brush = match vec3(material, "tangent", context)? {
+                Some(tangent) => Some((
+                    tangent,
+                    number(material, "tangent_fuzz", context)?.unwrap_or(fuzz),
+                    number(material, "bitangent_fuzz", context)?.unwrap_or(fuzz),
+                )),
+                None => None,
+            };
+            Ok(MaterialDesc::Metal {
+                albedo: albedo()?,
+                fuzz,
+                fresnel: flag(material, "fresnel", context)?.unwrap_or(false),
+                brush,
+            })
+        }
+        Some("mirror") => Ok(MaterialDesc::Mirror { albedo: albedo()? }),
+        Some("dielectric") => Ok(MaterialDesc::Dielectric {
+            ir: number(material, "ir", context)?
+                .ok_or_else(|| format!("{}: missing ir", context))?,
+        }),
+        Some("preset") => {
+            let name = material
+                .get("name")
+                .and_then(Json::as_str)
+                .ok_or_else(|| format!("{}: missing preset name", context))?;
+            material::preset(name).map_err(|err| format!("{}: {}", context, err))?;
+            Ok(MaterialDesc::Preset {
+                name: name.to_string(),
+            })
+        }
+        Some("diffuse_light") => Ok(MaterialDesc::DiffuseLight {
+            emit: vec3(material, "emit", context)?
+                .ok_or_else(|| format!("{}: missing emit", context))?,
+        }),
+        Some("shadow_catcher") => Ok(MaterialDesc::ShadowCatcher),
+        Some(kind) => Err(format!("{}: unknown material type {:?}", context, kind)),
+        None => Err(format!("{}: missing type", context)),
+    }
+}
+
+fn member<T: Into<Json>>(key: &str, value: T) -> (String, Json) {
+    (key.to_string(), value.into())
+}
+
+// Optional fields: missing is fine, present with the wrong shape is an error
+
+fn number(json: &Json, key: &str, context: &str) -> Result<Option<f64>, String> {
+    json.get(key)
+        .map(|value| {
+            value
+                .as_f64()
+                .ok_or_else(|| format!("{}.{} must be a number", context, key))
+        })
+        .transpose()
+}
+
+fn flag(json: &Json, key: &str, context: &str) -> Result<Option<bool>, String> {
+    json.get(key)
+        .map(|value| {
+            value
+                .as_bool()
+                .ok_or_else(|| format!("{}.{} must be true or false", context, key))
+        })
+        .transpose()
+}
+
+fn count(json: &Json, key: &str, context: &str) -> Result<Option<u64>, String> {
+    match number(json, key, context)? {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(Some(n as u64)),
+        Some(_) => Err(format!("{}.{} must be a whole number", context, key)),
+        None => Ok(None),
+    }
+}
+
+fn vec3(json: &Json, key: &str, context: &str) -> Result<Option<Vec3>, String> {
+    json.get(key)
+        .map(|value| Vec3::try_from(value).map_err(|err| format!("{}.{}: {}", context, key, err)))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::hit::Hit;
+    use crate::material::MaterialKind;
+    use crate::ray::Ray;
+    use crate::render::{render, Settings};
+
+    // Kind of the material seen straight down -z from the origin
+    fn kind_ahead(world: &World) -> MaterialKind {
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        world.hit(&r, 0.001, f64::INFINITY).unwrap().mat.kind()
+    }
+
+    #[test]
+    fn preset_materials_by_name() {
+        let scene = SceneFile::parse(
+            r#"{"objects": [{"type": "sphere", "center": [0, 0, -2], "radius": 0.5,
+                             "material": {"type": "preset", "name": "gold"}}]}"#,
+        )
+        .unwrap();
+        assert!(kind_ahead(&scene.build(WorldBuilder::new())) == MaterialKind::Specular);
+
+        let err = SceneFile::parse(
+            r#"{"objects": [{"type": "sphere", "center": [0, 0, -2], "radius": 0.5,
+                             "material": {"type": "preset", "name": "mithril"}}]}"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.starts_with("objects[0].material: ") && err.contains("\"mithril\""));
+    }
+
+    #[test]
+    fn diffuse_lights_are_registered_as_lights() {
+        let scene = SceneFile::parse(
+            r#"{"objects": [
+                  {"type": "sphere", "center": [0, 0, -2], "radius": 0.5,
+                   "material": {"type": "diffuse_light", "emit": [4, 4, 4]}},
+                  {"type": "sphere", "center": [0, -100.5, -2], "radius": 100,
+                   "material": {"type": "lambertian", "albedo": [0.5, 0.5, 0.5]}}]}"#,
+        )
+        .unwrap();
+        let world = scene.build(WorldBuilder::new());
+        assert!(kind_ahead(&world) == MaterialKind::Emissive);
+        assert_eq!(world.lights().count(), 1);
+        let light = world.lights().next().unwrap();
+        let emitted = light.material().unwrap().emitted();
+        assert_eq!((emitted.x(), emitted.y(), emitted.z()), (4.0, 4.0, 4.0));
+
+        let err = SceneFile::parse(
+            r#"{"objects": [{"type": "sphere", "center": [0, 0, -2], "radius": 0.5,
+                             "material": {"type": "diffuse_light"}}]}"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err, "objects[0].material: missing emit");
+    }
+
+    #[test]
+    fn shadow_catchers_by_type() {
+        let scene = SceneFile::parse(
+            r#"{"objects": [{"type": "sphere", "center": [0, 0, -2], "radius": 0.5,
+                             "material": {"type": "shadow_catcher"}}]}"#,
+        )
+        .unwrap();
+        let world = scene.build(WorldBuilder::new());
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(world
+            .hit(&r, 0.001, f64::INFINITY)
+            .unwrap()
+            .mat
+            .catches_shadows());
+    }
+
+    #[test]
+    fn written_scenes_parse_back_the_same() {
+        let scene = SceneFile::parse(
+            r#"{"cameras": [{"name": "near", "lookfrom": [0, 1, 2], "vfov": 40},
+                            {"name": "far", "lookfrom": [0, 2, 9], "aperture": 0}],
+                "image": {"width": 64, "aspect": 1.5},
+                "background": [0.1, 0.2, 0.3],
+                "objects": [
+                  {"type": "sphere", "center": [0, -100.5, -1], "radius": 100,
+                   "material": {"type": "shadow_catcher"}},
+                  {"type": "sphere", "center": [0.1, 0.2, 0.3], "radius": 0.5,
+                   "material": {"type": "lambertian", "albedo": [0.1, 0.7, 0.3],
+                                "normal_map": "bumps.ppm"}},
+                  {"type": "sphere", "center": [1, 0, -1], "radius": 0.5,
+                   "material": {"type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.3,
+                                "tangent": [0, 1, 0], "bitangent_fuzz": 0.05}},
+                  {"type": "sphere", "center": [2, 0, -1], "radius": 0.5,
+                   "material": {"type": "mirror", "albedo": [0.9, 0.9, 0.9]}},
+                  {"type": "sphere", "center": [3, 0, -1], "radius": 0.5,
+                   "material": {"type": "dielectric", "ir": 1.5}},
+                  {"type": "sphere", "center": [4, 0, -1], "radius": 0.5,
+                   "material": {"type": "preset", "name": "gold"}},
+                  {"type": "sphere", "center": [0, 5, 0], "radius": 1,
+                   "material": {"type": "diffuse_light", "emit": [4, 4, 4]}}]}"#,
+        )
+        .unwrap();
+        let written = scene.to_json().to_string();
+
+        let again = SceneFile::parse(&written).unwrap();
+        assert_eq!(again.to_json().to_string(), written);
+        assert_eq!(again.cameras[1].0, "far");
+        assert_eq!(again.cameras[0].1.vfov, 40.0);
+        assert_eq!(
+            (again.width, again.aspect, again.samples),
+            (Some(64), Some(1.5), None)
+        );
+
+        let env = SceneFile::parse(r#"{"background": "sky.ppm"}"#).unwrap();
+        let written = env.to_json().to_string();
+        assert!(written.contains(r#""background": "sky.ppm""#));
+        let file = include_str!("../scenes/random.json");
+        let written = SceneFile::parse(file).unwrap().to_json().to_string();
+        let again = SceneFile::parse(&written).unwrap();
+        assert_eq!(again.to_json().to_string(), written);
+    }
+
+    #[test]
+    fn named_cameras_render_different_images() {
+        let scene = SceneFile::parse(
+            r#"{"cameras": [
+                  {"name": "front", "lookfrom": [0, 0, 0], "lookat": [0, 0, -1], "vfov": 90},
+                  {"name": "side", "lookfrom": [3, 0, -2], "lookat": [0, 0, -2], "vfov": 40}],
+                "objects": [
+                  {"type": "sphere", "center": [0, 0, -2], "radius": 0.5,
+                   "material": {"type": "lambertian", "albedo": [0.8, 0.2, 0.2]}},
+                  {"type": "sphere", "center": [0, 0, -4], "radius": 1,
+                   "material": {"type": "lambertian", "albedo": [0.2, 0.2, 0.8]}}]}"#,
+        )
+        .unwrap();
+        let names: Vec<&str> = scene
+            .cameras
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["front", "side"]);
+        assert_eq!(named_camera(&scene.cameras, None).unwrap().vfov, 90.0);
+        assert_eq!(
+            named_camera(&scene.cameras, Some("side")).unwrap().vfov,
+            40.0
+        );
+        assert!(named_camera(&scene.cameras, Some("top"))
+            .err()
+            .unwrap()
+            .contains("front, side"));
+
+        let world = scene.build(WorldBuilder::new());
+        let settings = Settings {
+            seed: Some(1),
+            ..Settings::new(16, 16, 2)
+        };
+        let images: Vec<Vec<u64>> = scene
+            .cameras
+            .iter()
+            .map(|(_, view)| {
+                let cam = CameraBuilder::new()
+                    .lookfrom(view.lookfrom)
+                    .lookat(view.lookat)
+                    .vup(view.vup)
+                    .vfov(view.vfov)
+                    .aspect_ratio(1.0)
+                    .build();
+                render(&world, &cam, &settings)
+                    .pixels
+                    .iter()
+                    .map(|pixel| pixel.color.x().to_bits())
+                    .collect()
+            })
+            .collect();
+        assert_ne!(images[0], images[1]);
+    }
+
+    #[test]
+    fn camera_lists_are_checked() {
+        let err = |text: &str| SceneFile::parse(text).err().unwrap();
+        assert_eq!(
+            err(r#"{"camera": {}, "cameras": [{"name": "a"}]}"#),
+            "give either camera or cameras, not both"
+        );
+        assert_eq!(
+            err(r#"{"cameras": [{"vfov": 20}]}"#),
+            "cameras[0]: missing name"
+        );
+        assert_eq!(
+            err(r#"{"cameras": [{"name": "a"}, {"name": "a"}]}"#),
+            "cameras[1]: another camera is named \"a\""
+        );
+        assert_eq!(err(r#"{"cameras": []}"#), "cameras must not be empty");
+        let single = SceneFile::parse(r#"{"camera": {"vfov": 35}}"#).unwrap();
+        assert_eq!(single.cameras.len(), 1);
+        assert_eq!(single.cameras[0].0, "default");
+        assert_eq!(single.cameras[0].1.vfov, 35.0);
+    }
+}
@@ -0,0 +1,534 @@
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+use crate::cube::Cube;
+use crate::cylinder::Cylinder;
+use crate::disk::Disk;
+use crate::hit::{Background, World, WorldBuilder};
+use crate::material::{
+    self, DiffuseLight, Lambertian, MaterialKind, Metal, Mirror, Scatter, ShadowCatcher,
+};
+use crate::medium::ConstantMedium;
+use crate::perlin::Perlin;
+use crate::quad::Quad;
+use crate::rect::{XyRect, XzRect, YzRect};
+use crate::render::stream_rng;
+use crate::sphere::{MovingSphere, Sphere, SphereCap};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor};
+use crate::transform::{Moving, Placement, RotateY, Translate};
+use crate::triangle::Triangle;
+
+use super::vec::{Color, Point3, Vec3};
+
+// First stream of the random scene's grid cells, well clear of the per-pixel streams
+const CELL_STREAMS: u64 = 1 << 62;
+
+// Small spheres in a grid of cells from -extent to extent around three big ones. With
+// `bouncing` the diffuse spheres hop upwards while the shutter is open. Every cell draws from
+// its own stream, so cells are built in parallel and still come out the same for a seed.
+pub fn random_scene(world: WorldBuilder, extent: i32, bouncing: bool, seed: Option<u64>) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut world = world.sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground);
+
+    let cells: Vec<(i32, i32)> = (-extent..=extent)
+        .flat_map(|a| (-extent..=extent).map(move |b| (a, b)))
+        .collect();
+    // Collected in cell order, whichever thread finishes first
+    let spheres: Vec<(Point3, Option<Point3>, Arc<dyn Scatter>)> = cells
+        .par_iter()
+        .enumerate()
+        .map(|(idx, &(a, b))| {
+            let rng = &mut stream_rng(seed, CELL_STREAMS + idx as u64);
+            let choose_mat: f64 = rng.gen();
+            let center = Point3::new(
+                (a as f64) + rng.gen_range(0.0..0.9),
+                0.2,
+                (b as f64) + rng.gen_range(0.0..0.9),
+            );
+
+            let sphere_mat: Arc<dyn Scatter> = if choose_mat < 0.8 {
+                // Diffuse
+                let albedo = Color::random(0.0..1.0, rng) * Color::random(0.0..1.0, rng);
+                Arc::new(Lambertian::new(albedo))
+            } else if choose_mat < 0.95 {
+                // Metal
+                let albedo = Color::random(0.4..1.0, rng);
+                let fuzz = rng.gen_range(0.0..0.5);
+                Arc::new(Metal::new(albedo, fuzz))
+            } else {
+                // Glass
+                world.glass(1.5)
+            };
+
+            let center1 = (bouncing && sphere_mat.kind() == MaterialKind::Diffuse)
+                .then(|| center + Vec3::new(0.0, rng.gen_range(0.0..0.5), 0.0));
+            (center, center1, sphere_mat)
+        })
+        .collect();
+
+    for (center, center1, sphere_mat) in spheres {
+        world = match center1 {
+            Some(center1) => world.object(MovingSphere::new(
+                center, center1, 0.0, 1.0, 0.2, sphere_mat,
+            )),
+            None => world.sphere(center, 0.2, sphere_mat),
+        };
+    }
+
+    let mat1 = world.glass(1.5);
+    let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let mat3 = Arc::new(Mirror::new(Color::new(0.7, 0.6, 0.5)));
+
+    world
+        .sphere(Point3::new(0.0, 1.0, 0.0), 1.0, mat3)
+        .sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2)
+        .sphere(Point3::new(4.0, 1.0, 0.0), 1.0, mat1)
+        .build()
+}
+
+pub fn dome_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mat_dome = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1));
+    let mat_center = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3)));
+
+    // Upper hemisphere with an opening at the top
+    let dome = SphereCap::new(
+        Point3::new(0.0, 0.0, 0.0),
+        2.0,
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        90.0,
+        mat_dome,
+    );
+
+    world
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground)
+        .object(dome)
+        .sphere(Point3::new(0.0, 0.5, 0.0), 0.5, mat_center)
+        .build()
+}
+
+// Relative weights of the materials handed out in generated scenes
+#[derive(Clone, Copy)]
+pub struct MaterialMix {
+    pub diffuse: f64,
+    pub metal: f64,
+    pub glass: f64,
+}
+
+// Deterministic n x n x n cube of spheres centered on the origin, for benchmarking.
+// Materials come from a fixed seed, so every run renders the same scene.
+pub fn sphere_grid_scene(world: WorldBuilder, n: usize, spacing: f64, mix: MaterialMix) -> World {
+    const GRID_SEED: u64 = 0x5eed;
+    let mut rng = StdRng::seed_from_u64(GRID_SEED);
+    let total = mix.diffuse + mix.metal + mix.glass;
+    let offset = 0.5 * spacing * (n as f64 - 1.0);
+
+    let mut world = world;
+    for x in 0..n {
+        for y in 0..n {
+            for z in 0..n {
+                let center = Point3::new(
+                    x as f64 * spacing - offset,
+                    y as f64 * spacing - offset,
+                    z as f64 * spacing - offset,
+                );
+                let choose_mat = rng.gen_range(0.0..total);
+                let sphere_mat: Arc<dyn Scatter> = if choose_mat < mix.diffuse {
+                    Arc::new(Lambertian::new(Color::random(0.1..0.9, &mut rng)))
+                } else if choose_mat < mix.diffuse + mix.metal {
+                    let albedo = Color::random(0.5..1.0, &mut rng);
+                    Arc::new(Metal::new(albedo, rng.gen_range(0.0..0.3)))
+                } else {
+                    world.glass(1.5)
+                };
+                world = world.sphere(center, 0.3 * spacing, sphere_mat);
+            }
+        }
+    }
+    world.build()
+}
+
+// One sphere per named material preset, in a row across the view
+pub fn presets_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut world = world.sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground);
+
+    let names: Vec<&str> = material::preset_names().collect();
+    let spacing = 0.8;
+    let offset = 0.5 * spacing * (names.len() as f64 - 1.0);
+    for (idx, name) in names.iter().enumerate() {
+        let mat = material::preset(name).expect("listed presets resolve");
+        let center = Point3::new(0.0, 0.35, offset - idx as f64 * spacing);
+        world = world.sphere(center, 0.35, mat);
+    }
+    world.build()
+}
+
+// A few spheres in the air with no ground, for a look at the sky and --ground-color
+pub fn floating_scene(world: WorldBuilder) -> World {
+    let mat_left = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0));
+    let mat_center = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3)));
+    let mat_right = world.glass(1.5);
+
+    world
+        .sphere(Point3::new(0.0, 0.0, 2.2), 1.0, mat_left)
+        .sphere(Point3::new(0.0, 0.0, 0.0), 1.0, mat_center)
+        .sphere(Point3::new(0.0, 0.0, -2.2), 1.0, mat_right)
+        .build()
+}
+
+// A square pyramid of triangles next to a glass sphere
+pub fn pyramid_scene(world: WorldBuilder) -> World {
+    let checker = CheckerTexture::new(
+        Arc::new(SolidColor::new(Color::new(0.9, 0.9, 0.9))),
+        Arc::new(SolidColor::new(Color::new(0.2, 0.3, 0.1))),
+        std::f64::consts::PI,
+    );
+    let mat_ground = Arc::new(Lambertian::textured(Arc::new(checker)));
+    let mat_pyramid = Arc::new(Lambertian::new(Color::new(0.8, 0.6, 0.2)));
+    let mat_sphere = world.glass(1.5);
+
+    let apex = Point3::new(0.0, 2.0, 0.0);
+    let base = [
+        Point3::new(-1.5, 0.0, -1.5),
+        Point3::new(1.5, 0.0, -1.5),
+        Point3::new(1.5, 0.0, 1.5),
+        Point3::new(-1.5, 0.0, 1.5),
+    ];
+    let world = (0..4).fold(world, |world, i| {
+        let (a, b) = (base[i], base[(i + 1) % 4]);
+        world.object(Triangle::new(b, a, apex, mat_pyramid.clone()))
+    });
+
+    world
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground)
+        .object(Triangle::new(
+            base[0],
+            base[1],
+            base[2],
+            mat_pyramid.clone(),
+        ))
+        .object(Triangle::new(base[0], base[2], base[3], mat_pyramid))
+        .sphere(Point3::new(2.0, 1.0, 3.0), 1.0, mat_sphere)
+        .build()
+}
+
+// Cornell-style box around the origin, open towards the camera and lit from the ceiling
+fn cornell_box(world: WorldBuilder) -> WorldBuilder {
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+
+    let s = 1.5;
+    world
+        .background(Background::SolidColor(Color::new(0.0, 0.0, 0.0)))
+        .object(XyRect::new(-s, s, -s, s, -s, red))
+        .object(XyRect::new(-s, s, -s, s, s, green))
+        .object(YzRect::new(-s, s, -s, s, -s, white.clone()))
+        .object(XzRect::new(-s, s, -s, s, -s, white.clone()))
+        .object(XzRect::new(-s, s, -s, s, s, white))
+        .light(XzRect::new(-0.5, 0.5, -0.5, 0.5, s - 0.01, mat_lamp))
+}
+
+pub fn cornell_scene(world: WorldBuilder) -> World {
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let mat_glass = world.glass(1.5);
+
+    cornell_box(world)
+        .sphere(Point3::new(-0.5, -1.0, -0.6), 0.5, white)
+        .sphere(Point3::new(0.3, -1.0, 0.6), 0.5, mat_glass)
+        .build()
+}
+
+// The Cornell box with its classic tall and short block, each turned a little
+pub fn blocks_scene(world: WorldBuilder) -> World {
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let block = |height: f64| {
+        Cube::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.9, height, 0.9),
+            white.clone(),
+        )
+    };
+    let tall = RotateY::new(block(1.8), 15.0);
+    let short = RotateY::new(block(0.9), -18.0);
+
+    cornell_box(world)
+        .object(Translate::new(tall, Vec3::new(-1.1, -1.5, -1.0)))
+        .object(Translate::new(short, Vec3::new(-0.2, -1.5, 0.2)))
+        .build()
+}
+
+// The Cornell box with a ball of dark smoke and one of light fog
+pub fn smoke_scene(world: WorldBuilder) -> World {
+    // Only the shape of a medium's boundary matters, not its material
+    let boundary = Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0)));
+    let smoke = Sphere::new(Point3::new(-0.4, -0.8, -0.6), 0.7, boundary.clone());
+    let fog = Sphere::new(Point3::new(0.3, -0.8, 0.6), 0.7, boundary);
+
+    cornell_box(world)
+        .object(ConstantMedium::new(smoke, 2.0, Color::new(0.0, 0.0, 0.0)))
+        .object(ConstantMedium::new(fog, 2.0, Color::new(1.0, 1.0, 1.0)))
+        .build()
+}
+
+// Marble ground and sphere, the noise lattice comes from the scene's seeded stream
+pub fn marble_scene(world: WorldBuilder, rng: &mut dyn RngCore) -> World {
+    let marble = Arc::new(NoiseTexture::new(Perlin::new(rng), 4.0));
+    let mat_marble = Arc::new(Lambertian::textured(marble));
+    world
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_marble.clone())
+        .sphere(Point3::new(0.0, 2.0, 0.0), 2.0, mat_marble)
+        .build()
+}
+
+// One large sphere with an image mapped onto it, e.g. an equirectangular earth map
+pub fn globe_scene(world: WorldBuilder, texture: &str) -> World {
+    let mat_globe = Arc::new(Lambertian::textured(Arc::new(ImageTexture::new(texture))));
+    world
+        .sphere(Point3::new(0.0, 0.0, 0.0), 2.0, mat_globe)
+        .build()
+}
+
+// The globe turning 20 degrees while the shutter is open, so its surface blurs round
+// the axis while its outline stays sharp
+pub fn spin_scene(world: WorldBuilder, texture: &str) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mat_globe = Arc::new(Lambertian::textured(Arc::new(ImageTexture::new(texture))));
+    let globe = Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, mat_globe);
+    let center = Vec3::new(0.0, 2.0, 0.0);
+    world
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground)
+        .object(Moving::new(
+            globe,
+            Placement::new(1.0, 0.0, center),
+            Placement::new(1.0, 20.0, center),
+            0.0,
+            1.0,
+        ))
+        .build()
+}
+
+// A single glowing sphere over a dark background, the only source of light
+pub fn lamp_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mat_left = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3)));
+    let mat_right = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.2));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(8.0, 7.0, 5.0)));
+
+    world
+        .background(Background::SolidColor(Color::new(0.0, 0.0, 0.0)))
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground)
+        .sphere(Point3::new(0.0, 1.0, -2.0), 1.0, mat_left)
+        .sphere(Point3::new(0.0, 1.0, 2.0), 1.0, mat_right)
+        .light(Sphere::new(Point3::new(0.0, 3.5, 0.0), 0.5, mat_lamp))
+        .build()
+}
+
+// Disks for the ground and for a tilted lamp, where the lamp scene has spheres
+pub fn disk_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mat_ball = Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.7)));
+    let mat_metal = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(10.0, 9.0, 7.0)));
+
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    world
+        .background(Background::SolidColor(Color::new(0.0, 0.0, 0.0)))
+        .object(Disk::new(Point3::new(0.0, 0.0, 0.0), up, 6.0, mat_ground))
+        .sphere(Point3::new(0.0, 1.0, -1.2), 1.0, mat_ball)
+        .sphere(Point3::new(0.0, 0.6, 1.4), 0.6, mat_metal)
+        .light(Disk::new(
+            Point3::new(3.0, 4.0, 0.0),
+            Vec3::new(-0.6, -1.0, 0.0),
+            1.2,
+            mat_lamp,
+        ))
+        .build()
+}
+
+// A capped column, a capped drum lying on its side and an open metal tube tilted so it can be
+// looked into
+pub fn cylinders_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mat_column = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.2)));
+    let mat_drum = Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.7)));
+    let mat_tube = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.05));
+
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    world
+        .object(Disk::new(Point3::new(0.0, 0.0, 0.0), up, 8.0, mat_ground))
+        .object(Cylinder::new(
+            Point3::new(0.0, 0.0, -1.8),
+            up,
+            0.6,
+            2.2,
+            mat_column,
+        ))
+        .object(Cylinder::new(
+            Point3::new(0.0, 0.7, -0.2),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.7,
+            0.8,
+            mat_drum,
+        ))
+        .object(
+            Cylinder::new(
+                Point3::new(0.5, 0.05, 1.5),
+                Vec3::new(0.6, 0.5, 0.2),
+                0.4,
+                1.4,
+                mat_tube,
+            )
+            .with_caps(false),
+        )
+        .build()
+}
+
+// Parallelograms at odd angles, lit by a tilted quad light
+pub fn quads_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mat_red = Arc::new(Lambertian::new(Color::new(0.8, 0.2, 0.2)));
+    let mat_blue = Arc::new(Lambertian::new(Color::new(0.2, 0.3, 0.8)));
+    let mat_metal = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.7), 0.02));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(6.0, 6.0, 5.0)));
+
+    world
+        .background(Background::SolidColor(Color::new(0.05, 0.05, 0.08)))
+        .object(Quad::new(
+            Point3::new(-6.0, 0.0, -6.0),
+            Vec3::new(0.0, 0.0, 12.0),
+            Vec3::new(12.0, 0.0, 0.0),
+            mat_ground,
+        ))
+        .object(Quad::new(
+            Point3::new(-1.5, 0.0, -1.4),
+            Vec3::new(1.2, 0.0, 0.8),
+            Vec3::new(0.0, 1.5, 0.0),
+            mat_red,
+        ))
+        .object(Quad::new(
+            Point3::new(0.2, 0.3, 0.3),
+            Vec3::new(0.9, 0.0, -0.7),
+            Vec3::new(0.3, 1.2, 0.2),
+            mat_blue,
+        ))
+        .object(Quad::new(
+            Point3::new(-0.9, 0.0, 0.9),
+            Vec3::new(0.6, 0.0, 0.9),
+            Vec3::new(-0.3, 1.0, 0.0),
+            mat_metal,
+        ))
+        .light(Quad::new(
+            Point3::new(-1.0, 3.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.6, 2.0),
+            mat_lamp,
+        ))
+        .build()
+}
+
+// Diffuse spheres hovering at increasing heights under a single spherical light.
+// The closer an occluder is to the light, the wider its shadow's penumbra on the ground.
+pub fn penumbra_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.6, 0.6, 0.6)));
+    let mat_ball = Arc::new(Lambertian::new(Color::new(0.3, 0.4, 0.6)));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(15.0, 14.0, 12.0)));
+
+    let mut world = world
+        .background(Background::SolidColor(Color::new(0.0, 0.0, 0.0)))
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat_ground)
+        .light(Sphere::new(Point3::new(-2.0, 4.0, 0.0), 0.7, mat_lamp));
+    for (z, height) in [(-2.4, 0.4), (-0.8, 1.0), (0.8, 1.6), (2.4, 2.2)] {
+        world = world.sphere(Point3::new(0.0, height, z), 0.3, mat_ball.clone());
+    }
+    world.build()
+}
+
+// A post standing on the ground and a ball hovering just above it under a large rectangular
+// light. Their shadows are sharp where they meet the ground and soften with distance from it.
+pub fn contact_scene(world: WorldBuilder) -> World {
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7)));
+    let mat_post = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.2)));
+    let mat_ball = Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.7)));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(6.0, 6.0, 5.4)));
+
+    world
+        .background(Background::SolidColor(Color::new(0.0, 0.0, 0.0)))
+        .object(XzRect::new(-8.0, 8.0, -8.0, 8.0, 0.0, mat_ground))
+        .object(Cube::new(
+            Point3::new(-0.95, 0.0, 0.25),
+            Point3::new(-0.65, 1.4, 0.55),
+            mat_post,
+        ))
+        .sphere(Point3::new(0.8, 0.35, 1.5), 0.3, mat_ball)
+        .light(XzRect::new(-1.5, 1.5, 2.5, 4.0, 2.5, mat_lamp))
+        .build()
+}
+
+// Spheres on a shadow catcher under the sky and a lamp, to composite onto a photo with --alpha
+pub fn catcher_scene(world: WorldBuilder) -> World {
+    let mat_ball = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3)));
+    let mat_metal = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.05));
+    let mat_lamp = Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 9.0)));
+
+    world
+        .sphere(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(ShadowCatcher),
+        )
+        .sphere(Point3::new(0.0, 1.0, -1.2), 1.0, mat_ball)
+        .sphere(Point3::new(0.0, 0.6, 1.4), 0.6, mat_metal)
+        .light(Sphere::new(Point3::new(3.0, 5.0, 1.0), 0.8, mat_lamp))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::CameraBuilder;
+    use crate::render::{render, Settings};
+
+    // The random scene as seen from the default view, bit for bit
+    fn rendered(world: &World) -> Vec<[u64; 3]> {
+        let cam = CameraBuilder::new()
+            .lookfrom(Point3::new(13.0, 2.0, 3.0))
+            .lookat(Point3::new(0.0, 0.0, 0.0))
+            .vfov(20.0)
+            .aspect_ratio(16.0 / 9.0)
+            .build();
+        let settings = Settings {
+            seed: Some(1),
+            ..Settings::new(32, 18, 2)
+        };
+        render(world, &cam, &settings)
+            .pixels
+            .iter()
+            .map(|pixel| {
+                let c = pixel.color;
+                [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_scene_is_the_same_on_one_thread_or_many() {
+        let build_on = |threads: usize, seed: u64| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let world = pool.install(|| random_scene(WorldBuilder::new(), 5, true, Some(seed)));
+            rendered(&world)
+        };
+        let serial = build_on(1, 3);
+        assert_eq!(build_on(4, 3), serial);
+        assert_eq!(build_on(1, 3), serial);
+        // While another seed gives another scene
+        assert_ne!(build_on(4, 4), serial);
+    }
+}
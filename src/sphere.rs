@@ -1,5 +1,8 @@
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
+use crate::aabb::{surrounding_box, Aabb};
 use crate::material::Scatter;
 
 use super::hit::{Hit, HitRecord};
@@ -10,6 +13,8 @@ pub struct Sphere {
     center: Point3,
     radius: f64,
     mat: Arc<dyn Scatter>,
+    // Only sound for closed opaque spheres, which are never seen from inside
+    cull_backfaces: bool,
 }
 
 impl Sphere {
@@ -18,42 +23,472 @@ impl Sphere {
             center: cen,
             radius: r,
             mat: m,
+            cull_backfaces: false,
         }
     }
+
+    pub fn with_backface_culling(self, cull_backfaces: bool) -> Sphere {
+        Sphere {
+            cull_backfaces,
+            ..self
+        }
+    }
+
+    // Half-angle of the cone the sphere subtends as seen from `origin`
+    fn cos_theta_max(&self, origin: Point3) -> f64 {
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius.powi(2) {
+            // Seen from inside, the sphere covers every direction
+            return -1.0;
+        }
+        (1.0 - self.radius.powi(2) / distance_squared).sqrt()
+    }
 }
 
 impl Hit for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.origin() - self.center;
-        let a = r.direction().length_squared();
-        let half_b = oc.dot(r.direction());
-        let c = oc.length_squared() - self.radius.powi(2);
+        let (near, far) = intersect(self.center, self.radius, r)?;
 
-        let discriminant = half_b.powi(2) - a * c;
+        // Find the nearest root that lies in the acceptable range
+        let root = [near, far].iter().copied().find(|root| {
+            let culled =
+                self.cull_backfaces && (r.at(*root) - self.center).dot(r.direction()) > 0.0;
+            t_min <= *root && *root <= t_max && !culled
+        })?;
+
+        Some(record(r, root, self.center, self.radius, &self.mat))
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(sphere_box(self.center, self.radius))
+    }
 
-        if discriminant < 0.0 {
-            return None;
+    // Uniform over the subtended cone, so directions that miss the sphere have no density
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        match intersect(self.center, self.radius, &Ray::new(origin, direction, 0.0)) {
+            Some((_, far)) if far > 0.0 => 1.0 / (2.0 * PI * (1.0 - self.cos_theta_max(origin))),
+            _ => 0.0,
         }
+    }
 
-        // Find the nearest root that lies in the acceptable range
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let axis = (self.center - origin).normalized();
+        let cos_theta = 1.0 + rng.gen::<f64>() * (self.cos_theta_max(origin) - 1.0);
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+
+        let helper = if axis.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = axis.cross(helper).normalized();
+        let u = axis.cross(v);
+        phi.cos() * sin_theta * u + phi.sin() * sin_theta * v + cos_theta * axis
+    }
+}
+
+// Sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    mat: Arc<dyn Scatter>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        mat: Arc<dyn Scatter>,
+    ) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            mat,
+        }
+    }
+
+    // Holds still before time0 and after time1, which keeps it inside its bounding box
+    pub fn center(&self, time: f64) -> Point3 {
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl Hit for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let center = self.center(r.time());
+        let (near, far) = intersect(center, self.radius, r)?;
+
+        let root = [near, far]
+            .iter()
+            .copied()
+            .find(|root| t_min <= *root && *root <= t_max)?;
+
+        Some(record(r, root, center, self.radius, &self.mat))
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(surrounding_box(
+            sphere_box(self.center0, self.radius),
+            sphere_box(self.center1, self.radius),
+        ))
+    }
+}
+
+// Part of a sphere between two polar angles measured from the pole axis
+pub struct SphereCap {
+    center: Point3,
+    radius: f64,
+    axis: Vec3,
+    // Cosines of the polar angle bounds, cos_min >= cos_max
+    cos_min: f64,
+    cos_max: f64,
+    mat: Arc<dyn Scatter>,
+}
+
+impl SphereCap {
+    pub fn new(
+        center: Point3,
+        radius: f64,
+        pole: Vec3,
+        theta_min: f64,
+        theta_max: f64,
+        mat: Arc<dyn Scatter>,
+    ) -> SphereCap {
+        // Polar angles in degrees
+        let to_radians = std::f64::consts::PI / 180.0;
+        SphereCap {
+            center,
+            radius,
+            axis: pole.normalized(),
+            cos_min: (theta_min * to_radians).cos(),
+            cos_max: (theta_max * to_radians).cos(),
+            mat,
+        }
+    }
+}
+
+impl Hit for SphereCap {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (near, far) = intersect(self.center, self.radius, r)?;
+
+        // The far root is visible through the missing part of the sphere
+        let root = [near, far].iter().copied().find(|root| {
+            let cos_theta = (r.at(*root) - self.center).dot(self.axis) / self.radius;
+            t_min <= *root
+                && *root <= t_max
+                && self.cos_max <= cos_theta
+                && cos_theta <= self.cos_min
+        })?;
+
+        Some(record(r, root, self.center, self.radius, &self.mat))
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    // Along each axis the cap reaches out either to one of its two rim circles (a single point
+    // at a pole), or to the sphere's own extreme on that axis when it lies within the cap
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = |axis: usize| {
+            let a = self.axis[axis];
+            let across = (1.0 - a * a).max(0.0).sqrt();
+            let (mut lo, mut hi) = (f64::INFINITY, f64::NEG_INFINITY);
+            for cos_theta in [self.cos_min, self.cos_max] {
+                let rim = self.radius * cos_theta * a;
+                let half = self.radius * (1.0 - cos_theta * cos_theta).max(0.0).sqrt() * across;
+                lo = lo.min(rim - half);
+                hi = hi.max(rim + half);
             }
+            if self.cos_max <= -a && -a <= self.cos_min {
+                lo = -self.radius;
+            }
+            if self.cos_max <= a && a <= self.cos_min {
+                hi = self.radius;
+            }
+            (lo, hi)
+        };
+        let (x, y, z) = (extent(0), extent(1), extent(2));
+        Some(Aabb::new(
+            self.center + Vec3::new(x.0, y.0, z.0),
+            self.center + Vec3::new(x.1, y.1, z.1),
+        ))
+    }
+}
+
+fn sphere_box(center: Point3, radius: f64) -> Aabb {
+    let extent = Vec3::new(radius, radius, radius);
+    Aabb::new(center - extent, center + extent)
+}
+
+// Both roots of the ray-sphere quadratic, nearest first
+fn intersect(center: Point3, radius: f64, r: &Ray) -> Option<(f64, f64)> {
+    let oc = r.origin() - center;
+    let a = r.direction().length_squared();
+    let half_b = oc.dot(r.direction());
+    let c = oc.length_squared() - radius.powi(2);
+
+    let discriminant = half_b.powi(2) - a * c;
+
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    Some(((-half_b - sqrtd) / a, (-half_b + sqrtd) / a))
+}
+
+fn record(r: &Ray, root: f64, center: Point3, radius: f64, mat: &Arc<dyn Scatter>) -> HitRecord {
+    let mut rec = HitRecord {
+        t: root,
+        p: r.at(root),
+        mat: mat.clone(),
+        normal: Vec3::new(0.0, 0.0, 0.0),
+        u: 0.0,
+        v: 0.0,
+        front_face: false,
+    };
+    let outward_normal = (rec.p - center) / radius;
+    rec.set_face_normal(r, outward_normal);
+    (rec.u, rec.v) = sphere_uv(outward_normal);
+    rec
+}
+
+// Longitude from -x around through +z, and latitude from the bottom pole, both in 0..1:
+// u is 0.25 at +z, 0.5 at +x and 0.75 at -z
+fn sphere_uv(p: Point3) -> (f64, f64) {
+    let theta = (-p.y()).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::Color;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_uv(p: Point3, u: f64, v: f64) {
+        let uv = sphere_uv(p);
+        assert!(
+            (uv.0 - u).abs() < 1e-12 && (uv.1 - v).abs() < 1e-12,
+            "uv of {} is {:?}, expected ({}, {})",
+            p,
+            uv,
+            u,
+            v
+        );
+    }
+
+    #[test]
+    fn uv_runs_around_from_minus_x_and_up_from_the_bottom() {
+        assert_uv(Point3::new(1.0, 0.0, 0.0), 0.5, 0.5);
+        assert_uv(Point3::new(0.0, 0.0, 1.0), 0.25, 0.5);
+        assert_uv(Point3::new(0.0, 0.0, -1.0), 0.75, 0.5);
+        assert_uv(Point3::new(0.0, 1.0, 0.0), 0.5, 1.0);
+        assert_uv(Point3::new(0.0, -1.0, 0.0), 0.5, 0.0);
+    }
+
+    #[test]
+    fn hit_record_carries_the_uv_of_the_outward_normal() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 2.0, mat);
+        // Hits the sphere on its +x side
+        let r = Ray::new(Point3::new(5.0, 0.0, -3.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = sphere.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert!((rec.u - 0.5).abs() < 1e-12);
+        assert!((rec.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn culled_back_face_is_not_hit() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let culled = Sphere::new(Point3::new(0.0, 0.0, -3.0), 2.0, mat).with_backface_culling(true);
+        // From inside only the back face is in front of the ray
+        let inside = Ray::new(Point3::new(0.0, 0.0, -3.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(culled.hit(&inside, 0.001, f64::INFINITY).is_none());
+        // With the front face out of range, the far side is not hit either
+        let outside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(culled.hit(&outside, 2.0, f64::INFINITY).is_none());
+        let rec = culled.hit(&outside, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12 && rec.front_face);
+
+        // Without culling the inside is hit as a back face
+        let open = culled.with_backface_culling(false);
+        let rec = open.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12 && !rec.front_face);
+    }
+
+    #[test]
+    fn light_samples_fall_within_the_subtended_cone() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let center = Point3::new(1.0, 2.0, -5.0);
+        let bulb = Sphere::new(center, 1.5, mat);
+        let origin = Point3::new(0.0, 0.5, 1.0);
+        let axis = (center - origin).normalized();
+        let cos_theta_max = (1.0 - 1.5f64.powi(2) / (center - origin).length_squared()).sqrt();
+
+        // Uniform over the cone's solid angle
+        let pdf = bulb.pdf_value(origin, axis);
+        assert!((pdf - 1.0 / (2.0 * PI * (1.0 - cos_theta_max))).abs() < 1e-12);
+
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut near_the_rim = 0;
+        for _ in 0..10_000 {
+            let direction = bulb.random(origin, &mut rng);
+            assert!((direction.length() - 1.0).abs() < 1e-12);
+            let cos_theta = direction.dot(axis);
+            assert!(cos_theta >= cos_theta_max - 1e-12);
+            near_the_rim += (cos_theta < cos_theta_max + 1e-4) as u32;
+            // Every sampled direction leads to the sphere, with the same density
+            let r = Ray::new(origin, direction, 0.0);
+            assert!(bulb.hit(&r, 0.001, f64::INFINITY).is_some());
+            assert!((bulb.pdf_value(origin, direction) - pdf).abs() < 1e-12);
         }
+        // The whole cone is reached, out to its edge
+        assert!(near_the_rim > 0);
 
-        let mut rec = HitRecord {
-            t: root,
-            p: r.at(root),
-            mat: self.mat.clone(),
-            normal: Vec3::new(0.0, 0.0, 0.0),
-            front_face: false,
+        // Directions that miss have no density
+        assert_eq!(bulb.pdf_value(origin, -axis), 0.0);
+    }
+
+    #[test]
+    fn moving_sphere_is_hit_where_it_is_at_the_ray_time() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let moving = MovingSphere::new(
+            Point3::new(0.0, 0.0, -3.0),
+            Point3::new(0.0, 0.8, -3.0),
+            0.0,
+            1.0,
+            0.5,
+            mat.clone(),
+        );
+        let hit_at = |time: f64| {
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+            moving.hit(&r, 0.001, f64::INFINITY)
         };
-        let outward_normal = (rec.p - self.center) / self.radius;
-        rec.set_face_normal(r, outward_normal);
-        Some(rec)
+        // Head on at the start, further back once it has risen, and gone from the ray by the end
+        let start = hit_at(0.0).unwrap();
+        assert!((start.p - Point3::new(0.0, 0.0, -2.5)).length() < 1e-12);
+        let middle = hit_at(0.5).unwrap();
+        assert!((middle.p - Point3::new(0.0, 0.0, -2.7)).length() < 1e-12);
+        assert!(hit_at(1.0).is_none());
+
+        // A still sphere ignores the time
+        let still = Sphere::new(Point3::new(0.0, 0.0, -3.0), 0.5, mat);
+        for time in [0.0, 0.5, 1.0, 7.0] {
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+            let rec = still.hit(&r, 0.001, f64::INFINITY).unwrap();
+            assert!((rec.t - 2.5).abs() < 1e-12);
+        }
+    }
+
+    fn dome(theta_max: f64) -> SphereCap {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let pole = Vec3::new(0.0, 1.0, 0.0);
+        SphereCap::new(Point3::new(0.0, 0.0, -3.0), 2.0, pole, 0.0, theta_max, mat)
+    }
+
+    #[test]
+    fn cap_hits_inside_its_polar_range_only() {
+        // Straight down onto the pole, inside a 30 degree cap
+        let down = Ray::new(Point3::new(0.0, 5.0, -3.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = dome(30.0).hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+
+        // At the equator, 90 degrees from the pole, where the sphere would be hit
+        let side = Ray::new(Point3::new(5.0, 0.0, -3.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(dome(30.0).hit(&side, 0.001, f64::INFINITY).is_none());
+        assert!(dome(100.0).hit(&side, 0.001, f64::INFINITY).is_some());
+
+        // From below the missing bottom lets the ray through to the inside of the cap
+        let up = Ray::new(Point3::new(0.0, -5.0, -3.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let rec = dome(30.0).hit(&up, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 7.0).abs() < 1e-12);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn cap_box_is_tight_to_the_cap() {
+        let sphere = sphere_box(Point3::new(0.0, 0.0, -3.0), 2.0);
+        let small = dome(30.0).bounding_box().unwrap();
+        for axis in 0..3 {
+            assert!(small.minimum[axis] > sphere.minimum[axis]);
+            assert!(small.maximum[axis] <= sphere.maximum[axis]);
+        }
+        // The rim is at height 2 cos 30 with radius 2 sin 30 = 1, the top is the pole
+        assert!((small.minimum - Point3::new(-1.0, 3.0f64.sqrt(), -4.0)).length() < 1e-12);
+        assert!((small.maximum - Point3::new(1.0, 2.0, -2.0)).length() < 1e-12);
+
+        // A zone around the equator reaches out to the full radius sideways
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let zone = SphereCap::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            120.0,
+            mat,
+        );
+        let zone = zone.bounding_box().unwrap();
+        assert!((zone.minimum - Point3::new(-1.0, -0.5, -1.0)).length() < 1e-12);
+        assert!((zone.maximum - Point3::new(1.0, 0.5, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn tilted_cap_box_encloses_its_surface() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let pole = Vec3::new(1.0, 2.0, -0.5);
+        let cap = SphereCap::new(Point3::new(1.0, -1.0, 2.0), 1.5, pole, 20.0, 70.0, mat);
+        let bbox = cap.bounding_box().unwrap();
+
+        // Every ray from the center that hits the cap hits it inside the box
+        let mut touched = [[false; 2]; 3];
+        let steps = 400;
+        for i in 0..steps {
+            for j in 0..steps {
+                let theta = PI * (i as f64 + 0.5) / steps as f64;
+                let phi = 2.0 * PI * j as f64 / steps as f64;
+                let dir = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let r = Ray::new(Point3::new(1.0, -1.0, 2.0), dir, 0.0);
+                if let Some(rec) = cap.hit(&r, 0.001, f64::INFINITY) {
+                    for (axis, touched) in touched.iter_mut().enumerate() {
+                        let (lo, hi) = (bbox.minimum[axis], bbox.maximum[axis]);
+                        assert!(lo - 1e-9 <= rec.p[axis] && rec.p[axis] <= hi + 1e-9);
+                        touched[0] |= rec.p[axis] - lo < 0.02;
+                        touched[1] |= hi - rec.p[axis] < 0.02;
+                    }
+                }
+            }
+        }
+        // And the cap reaches every face of it
+        assert_eq!(touched, [[true; 2]; 3]);
     }
 }
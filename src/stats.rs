@@ -0,0 +1,87 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Ray and intersection counts for --stats, off unless enabled. Each thread counts on its own
+// and adds to the totals once a pixel is done, so tracing never waits on shared counters.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TOTALS: Mutex<Stats> = Mutex::new(Stats::new());
+
+thread_local! {
+    static LOCAL: Cell<Stats> = const { Cell::new(Stats::new()) };
+}
+
+#[derive(Clone, Copy)]
+pub struct Stats {
+    // Camera rays, one per sample
+    pub primary_rays: u64,
+    // Rays leaving a surface after a bounce, every child of a split included
+    pub scatter_rays: u64,
+    // Rays towards a light for next-event estimation
+    pub shadow_rays: u64,
+    // Objects tested against a ray, BVH leaves and unbounded objects
+    pub hit_tests: u64,
+}
+
+impl Stats {
+    const fn new() -> Stats {
+        Stats {
+            primary_rays: 0,
+            scatter_rays: 0,
+            shadow_rays: 0,
+            hit_tests: 0,
+        }
+    }
+
+    fn add(&mut self, other: Stats) {
+        self.primary_rays += other.primary_rays;
+        self.scatter_rays += other.scatter_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.hit_tests += other.hit_tests;
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+// Counts something on this thread, a single relaxed load when stats are off
+#[inline]
+pub fn record(count: impl FnOnce(&mut Stats)) {
+    if ENABLED.load(Ordering::Relaxed) {
+        LOCAL.with(|local| {
+            let mut stats = local.get();
+            count(&mut stats);
+            local.set(stats);
+        });
+    }
+}
+
+// Moves this thread's counts into the totals, at the end of every parallel work item
+pub fn flush() {
+    if ENABLED.load(Ordering::Relaxed) {
+        let local = LOCAL.with(|local| local.replace(Stats::new()));
+        TOTALS.lock().expect("stats lock").add(local);
+    }
+}
+
+pub fn print(elapsed: Duration) {
+    let stats = *TOTALS.lock().expect("stats lock");
+    let rays = stats.primary_rays + stats.scatter_rays + stats.shadow_rays;
+    let per = |count: u64, of: u64| count as f64 / of.max(1) as f64;
+    eprintln!(
+        "Stats: {} primary, {} scatter and {} shadow rays, {:.2} bounces per primary ray",
+        stats.primary_rays,
+        stats.scatter_rays,
+        stats.shadow_rays,
+        per(stats.scatter_rays, stats.primary_rays)
+    );
+    eprintln!(
+        "Stats: {} hit tests, {:.1} per ray, {:.3} Mrays/s over {:.2} s",
+        stats.hit_tests,
+        per(stats.hit_tests, rays),
+        rays as f64 / elapsed.as_secs_f64().max(1.0e-9) / 1.0e6,
+        elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,357 @@
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use crate::perlin::Perlin;
+
+use super::vec::{Color, Point3, Vec3};
+
+pub trait Texture: Send + Sync {
+    // Color at surface coordinates (u, v) of the hit point `p`
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+// Alternates between two textures in space, a cell is pi / scale wide along each axis
+pub struct CheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f64) -> CheckerTexture {
+        CheckerTexture { even, odd, scale }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let sines =
+            (self.scale * p.x()).sin() * (self.scale * p.y()).sin() * (self.scale * p.z()).sin();
+        if sines < 0.0 {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+}
+
+// What a gradient runs along
+#[derive(Clone, Copy)]
+pub enum GradientDirection {
+    // Surface coordinates, 0 to 1
+    U,
+    V,
+    // Position in space, from `origin` at 0 to `origin + axis` at 1
+    Axis { origin: Point3, axis: Vec3 },
+}
+
+// Blends linearly from `start` to `end`, and holds them beyond either end
+pub struct GradientTexture {
+    pub start: Color,
+    pub end: Color,
+    pub direction: GradientDirection,
+}
+
+impl GradientTexture {
+    pub fn new(start: Color, end: Color, direction: GradientDirection) -> GradientTexture {
+        GradientTexture {
+            start,
+            end,
+            direction,
+        }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let t = match self.direction {
+            GradientDirection::U => u,
+            GradientDirection::V => v,
+            GradientDirection::Axis { origin, axis } => {
+                (*p - origin).dot(axis) / axis.length_squared()
+            }
+        };
+        self.start.lerp(self.end, t.clamp(0.0, 1.0))
+    }
+}
+
+// Grayscale marble, stripes along z disturbed by Perlin turbulence
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(noise: Perlin, scale: f64) -> NoiseTexture {
+        NoiseTexture { noise, scale }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let stripes = 0.5 * (1.0 + (self.scale * p.z() + 10.0 * self.noise.turb(p, 7)).sin());
+        Color::new(1.0, 1.0, 1.0) * stripes
+    }
+}
+
+// Image wrapped around by the hit's (u, v), with v = 0 at the bottom row
+pub struct ImageTexture {
+    // Linear RGB, row by row from the top, empty if the image could not be loaded
+    data: Vec<Color>,
+    width: usize,
+    height: usize,
+    // Value everywhere when the image could not be loaded
+    missing: Color,
+}
+
+impl ImageTexture {
+    // A netpbm image (P3 or P6), the same formats the renderer writes
+    pub fn new(path: &str) -> ImageTexture {
+        // Magenta stands out where a texture went missing
+        ImageTexture::load(path, true, Color::new(1.0, 0.0, 1.0))
+    }
+
+    // Tangent-space normals, taken as they are stored rather than gamma decoded. Without
+    // the file it is flat, so the surface keeps its own normal.
+    pub fn normal_map(path: &str) -> ImageTexture {
+        ImageTexture::load(path, false, Color::new(0.5, 0.5, 1.0))
+    }
+
+    // False when it stands in for an image that could not be read
+    pub fn is_loaded(&self) -> bool {
+        !self.data.is_empty()
+    }
+
+    // The image as `new` reads it, or why it could not be read, for callers that report it
+    // themselves
+    pub fn open(path: &str) -> io::Result<ImageTexture> {
+        let (width, height, data) = read_ppm(path, true)?;
+        Ok(ImageTexture {
+            data,
+            width,
+            height,
+            missing: Color::new(1.0, 0.0, 1.0),
+        })
+    }
+
+    fn load(path: &str, decode_gamma: bool, missing: Color) -> ImageTexture {
+        match read_ppm(path, decode_gamma) {
+            Ok((width, height, data)) => ImageTexture {
+                data,
+                width,
+                height,
+                missing,
+            },
+            Err(err) => {
+                eprintln!("warning: cannot load texture {:?}: {}", path, err);
+                ImageTexture {
+                    data: Vec::new(),
+                    width: 0,
+                    height: 0,
+                    missing,
+                }
+            }
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        if self.data.is_empty() {
+            return self.missing;
+        }
+
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.data[j * self.width + i]
+    }
+}
+
+fn read_ppm(path: &str, decode_gamma: bool) -> io::Result<(usize, usize, Vec<Color>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let bytes = fs::read(path)?;
+    let mut pos = 0;
+
+    let magic = next_field(&bytes, &mut pos);
+    let number = |pos: &mut usize| {
+        next_field(&bytes, pos)
+            .and_then(|field| field.parse::<usize>().ok())
+            .ok_or_else(|| invalid("malformed header"))
+    };
+    let (width, height, maxval) = (number(&mut pos)?, number(&mut pos)?, number(&mut pos)?);
+    if width == 0 || height == 0 || maxval == 0 {
+        return Err(invalid("empty image"));
+    }
+
+    let len = 3 * width * height;
+    let samples: Vec<usize> = match magic {
+        // A single whitespace byte separates the header from the raster
+        Some("P6") if maxval < 256 => bytes
+            .get(pos + 1..pos + 1 + len)
+            .ok_or_else(|| invalid("truncated raster"))?
+            .iter()
+            .map(|sample| *sample as usize)
+            .collect(),
+        Some("P3") => (0..len)
+            .map(|_| number(&mut pos))
+            .collect::<Result<_, _>>()?,
+        _ => return Err(invalid("expected a P3 or P6 image with 8-bit samples")),
+    };
+
+    // Undo the gamma 2 that the renderer encodes its output with
+    let linear = |sample: usize| {
+        let value = sample.min(maxval) as f64 / maxval as f64;
+        if decode_gamma {
+            value.powi(2)
+        } else {
+            value
+        }
+    };
+    let data = samples
+        .chunks(3)
+        .map(|rgb| Color::new(linear(rgb[0]), linear(rgb[1]), linear(rgb[2])))
+        .collect();
+    Ok((width, height, data))
+}
+
+// Next whitespace separated token of a netpbm header, skipping comments
+fn next_field<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    loop {
+        match bytes.get(*pos)? {
+            b'#' => {
+                while bytes.get(*pos).is_some_and(|b| *b != b'\n') {
+                    *pos += 1;
+                }
+            }
+            b if b.is_ascii_whitespace() => *pos += 1,
+            _ => break,
+        }
+    }
+    let start = *pos;
+    while bytes.get(*pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    std::str::from_utf8(&bytes[start..*pos]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn assert_close(a: Color, b: Color) {
+        assert!((a - b).length() < 1.0e-12, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn gradient_midpoint_is_the_average_of_the_ends() {
+        let (start, end) = (Color::new(0.1, 0.2, 0.9), Color::new(0.9, 0.6, 0.1));
+        let average = 0.5 * (start + end);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        let by_u = GradientTexture::new(start, end, GradientDirection::U);
+        assert_close(by_u.value(0.5, 0.9, &origin), average);
+        assert_close(by_u.value(0.0, 0.9, &origin), start);
+        assert_close(by_u.value(1.0, 0.9, &origin), end);
+        let by_v = GradientTexture::new(start, end, GradientDirection::V);
+        assert_close(by_v.value(0.9, 0.5, &origin), average);
+    }
+
+    #[test]
+    fn gradient_along_an_axis_holds_its_ends() {
+        let (start, end) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let vertical = GradientTexture::new(
+            start,
+            end,
+            GradientDirection::Axis {
+                origin: Point3::new(0.0, -1.0, 0.0),
+                axis: Vec3::new(0.0, 4.0, 0.0),
+            },
+        );
+        let at = |y: f64| vertical.value(0.0, 0.0, &Point3::new(3.0, y, -2.0));
+        assert_close(at(1.0), Color::new(0.5, 0.5, 0.5));
+        assert_close(at(0.0), Color::new(0.25, 0.25, 0.25));
+        assert_close(at(-5.0), start);
+        assert_close(at(10.0), end);
+    }
+
+    #[test]
+    fn image_corners_sample_their_pixels() {
+        // 2x2 checker: red and green on top, blue and white below
+        let path = std::env::temp_dir().join(format!("rayrust-checker-{}.ppm", std::process::id()));
+        fs::write(
+            &path,
+            "P3\n2 2\n255\n255 0 0  0 255 0\n0 0 255  255 255 255\n",
+        )
+        .unwrap();
+        let image = ImageTexture::new(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(image.is_loaded());
+
+        // v = 0 is the bottom row, and u and v are clamped to the edges
+        let at = |u: f64, v: f64| image.value(u, v, &Point3::new(0.0, 0.0, 0.0));
+        assert_close(at(0.0, 1.0), Color::new(1.0, 0.0, 0.0));
+        assert_close(at(1.0, 1.0), Color::new(0.0, 1.0, 0.0));
+        assert_close(at(0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+        assert_close(at(1.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        assert_close(at(-3.0, 7.0), Color::new(1.0, 0.0, 0.0));
+        assert_close(at(0.25, 0.75), Color::new(1.0, 0.0, 0.0));
+        assert_close(at(0.75, 0.25), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn missing_image_is_magenta() {
+        let image = ImageTexture::new("/nonexistent/rayrust-texture.ppm");
+        assert!(!image.is_loaded());
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        assert_close(image.value(0.3, 0.6, &origin), Color::new(1.0, 0.0, 1.0));
+        // A missing normal map is flat instead
+        let normals = ImageTexture::normal_map("/nonexistent/rayrust-normals.ppm");
+        assert_close(normals.value(0.3, 0.6, &origin), Color::new(0.5, 0.5, 1.0));
+    }
+
+    #[test]
+    fn marble_stays_within_zero_and_one() {
+        let marble = NoiseTexture::new(Perlin::new(&mut StdRng::seed_from_u64(5)), 4.0);
+        let reseeded = NoiseTexture::new(Perlin::new(&mut StdRng::seed_from_u64(5)), 4.0);
+        let (mut lowest, mut highest) = (f64::INFINITY, f64::NEG_INFINITY);
+        for i in -12..12 {
+            for j in -12..12 {
+                for k in -12..12 {
+                    let p = Point3::new(i as f64 * 0.37, j as f64 * 0.29, k as f64 * 0.41);
+                    let color = marble.value(0.0, 0.0, &p);
+                    for channel in 0..3 {
+                        assert!((0.0..=1.0).contains(&color[channel]), "{} at {}", color, p);
+                    }
+                    lowest = lowest.min(color.x());
+                    highest = highest.max(color.x());
+                    // The same seed gives the same marble
+                    assert_eq!(reseeded.value(0.0, 0.0, &p).x(), color.x());
+                }
+            }
+        }
+        // And the stripes run through most of that range
+        assert!(lowest < 0.05 && highest > 0.95, "{}..{}", lowest, highest);
+    }
+}
@@ -0,0 +1,435 @@
+use rand::RngCore;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::material::Scatter;
+
+use super::hit::{Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Point3, Vec3};
+
+// An object moved by `offset`, the ray is moved the other way instead
+pub struct Translate {
+    object: Box<dyn Hit>,
+    offset: Vec3,
+}
+
+impl Translate {
+    pub fn new<H: Hit + 'static>(object: H, offset: Vec3) -> Translate {
+        Translate {
+            object: Box::new(object),
+            offset,
+        }
+    }
+}
+
+impl Hit for Translate {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let moved = Ray::new(r.origin() - self.offset, r.direction(), r.time());
+        let mut rec = self.object.hit(&moved, t_min, t_max)?;
+        rec.p += self.offset;
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        self.object.material()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self.object.bounding_box()?;
+        Some(Aabb::new(
+            bbox.minimum + self.offset,
+            bbox.maximum + self.offset,
+        ))
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.object.random(origin - self.offset, rng)
+    }
+}
+
+// An object turned about the +y axis, the ray is turned into the object's frame instead
+pub struct RotateY {
+    object: Box<dyn Hit>,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Option<Aabb>,
+}
+
+impl RotateY {
+    // Angle in degrees, counter-clockwise looking down the y axis
+    pub fn new<H: Hit + 'static>(object: H, angle: f64) -> RotateY {
+        let (sin_theta, cos_theta) = angle.to_radians().sin_cos();
+        let mut rotated = RotateY {
+            object: Box::new(object),
+            sin_theta,
+            cos_theta,
+            bbox: None,
+        };
+
+        // Box around the turned corners of the object's own box
+        rotated.bbox = rotated.object.bounding_box().map(|bbox| {
+            let corner = |i: usize| {
+                let pick = |axis: usize| {
+                    if i & (1 << axis) == 0 {
+                        bbox.minimum[axis]
+                    } else {
+                        bbox.maximum[axis]
+                    }
+                };
+                rotated.to_world(Point3::new(pick(0), pick(1), pick(2)))
+            };
+            (1..8)
+                .map(corner)
+                .fold(Aabb::new(corner(0), corner(0)), |bbox, p| {
+                    surrounding_box(bbox, Aabb::new(p, p))
+                })
+        });
+        rotated
+    }
+
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+}
+
+impl Hit for RotateY {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let turned = Ray::new(
+            self.to_object(r.origin()),
+            self.to_object(r.direction()),
+            r.time(),
+        );
+        // A rotation keeps the sign of direction·normal, so front_face still holds
+        let mut rec = self.object.hit(&turned, t_min, t_max)?;
+        rec.p = self.to_world(rec.p);
+        rec.normal = self.to_world(rec.normal);
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        self.object.material()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object
+            .pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.to_world(self.object.random(self.to_object(origin), rng))
+    }
+}
+
+// Where a moving object is at one end of its motion: scaled by `scale` about the origin,
+// turned `angle` degrees about the +y axis like RotateY, then moved by `offset`. The scale
+// must not be zero.
+#[derive(Clone, Copy)]
+pub struct Placement {
+    pub scale: f64,
+    pub angle: f64,
+    pub offset: Vec3,
+}
+
+impl Placement {
+    pub fn new(scale: f64, angle: f64, offset: Vec3) -> Placement {
+        Placement {
+            scale,
+            angle,
+            offset,
+        }
+    }
+
+    fn lerp(self, other: Placement, s: f64) -> Placement {
+        Placement {
+            scale: self.scale + s * (other.scale - self.scale),
+            angle: self.angle + s * (other.angle - self.angle),
+            offset: self.offset.lerp(other.offset, s),
+        }
+    }
+
+    fn to_world(self, p: Point3) -> Point3 {
+        self.scale * p.rotate_y(self.angle.to_radians()) + self.offset
+    }
+}
+
+// An object moving from one placement at `time0` to another at `time1`, interpolated by the
+// ray's time. Spinning or growing objects blur over the shutter like MovingSphere does.
+pub struct Moving {
+    object: Box<dyn Hit>,
+    start: Placement,
+    end: Placement,
+    time0: f64,
+    time1: f64,
+    bbox: Option<Aabb>,
+}
+
+impl Moving {
+    pub fn new<H: Hit + 'static>(
+        object: H,
+        start: Placement,
+        end: Placement,
+        time0: f64,
+        time1: f64,
+    ) -> Moving {
+        let bbox = object.bounding_box().map(|bbox| {
+            let corners: Vec<Point3> = (0..8)
+                .map(|i| {
+                    let pick = |axis: usize| {
+                        if i & (1 << axis) == 0 {
+                            bbox.minimum[axis]
+                        } else {
+                            bbox.maximum[axis]
+                        }
+                    };
+                    Point3::new(pick(0), pick(1), pick(2))
+                })
+                .collect();
+            let around = |placement: Placement| {
+                let mut placed = corners.iter().map(|corner| placement.to_world(*corner));
+                let first = placed.next().unwrap();
+                placed.fold(Aabb::new(first, first), |bbox, p| {
+                    surrounding_box(bbox, Aabb::new(p, p))
+                })
+            };
+            if start.angle == end.angle {
+                // Every corner then moves in a straight line, so the ends bound it
+                surrounding_box(around(start), around(end))
+            } else {
+                // Turning, a corner stays within its distance from the y axis
+                let reach = corners
+                    .iter()
+                    .map(|corner| corner.x().hypot(corner.z()))
+                    .fold(0.0, f64::max);
+                let extent = |placement: Placement| {
+                    let reach = placement.scale.abs() * reach;
+                    let (y0, y1) = (
+                        placement.scale * bbox.minimum.y(),
+                        placement.scale * bbox.maximum.y(),
+                    );
+                    Aabb::new(
+                        Point3::new(-reach, y0.min(y1), -reach) + placement.offset,
+                        Point3::new(reach, y0.max(y1), reach) + placement.offset,
+                    )
+                };
+                surrounding_box(extent(start), extent(end))
+            }
+        });
+        Moving {
+            object: Box::new(object),
+            start,
+            end,
+            time0,
+            time1,
+            bbox,
+        }
+    }
+
+    // Holds still before time0 and after time1, which keeps it inside its bounding box
+    fn placement(&self, time: f64) -> Placement {
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.start.lerp(self.end, s)
+    }
+}
+
+impl Hit for Moving {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let placement = self.placement(r.time());
+        let angle = placement.angle.to_radians();
+        // The inverse of the placement keeps t, so the range carries over as it is
+        let placed = Ray::new(
+            ((r.origin() - placement.offset) / placement.scale).rotate_y(-angle),
+            (r.direction() / placement.scale).rotate_y(-angle),
+            r.time(),
+        );
+        let mut rec = self.object.hit(&placed, t_min, t_max)?;
+        rec.p = placement.to_world(rec.p);
+        // A uniform scale and a rotation keep normals normal, and front_face as it is
+        rec.normal = (placement.scale.signum() * rec.normal).rotate_y(angle);
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        self.object.material()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::cube::Cube;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec::Color;
+
+    fn unit_sphere() -> Sphere {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, mat)
+    }
+
+    fn still(offset: Vec3) -> Placement {
+        Placement::new(1.0, 0.0, offset)
+    }
+
+    #[test]
+    fn spinning_turns_the_surface_with_the_ray_time() {
+        let offset = Vec3::new(0.0, 0.0, -5.0);
+        let spinning = Moving::new(
+            unit_sphere(),
+            still(offset),
+            Placement::new(1.0, 90.0, offset),
+            0.0,
+            1.0,
+        );
+        let at = |time: f64| {
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+            spinning.hit(&r, 0.001, f64::INFINITY).unwrap()
+        };
+        let (first, last) = (at(0.0), at(1.0));
+        // The same point in space, but a quarter turn further round the surface
+        assert!((first.t - 4.0).abs() < 1e-12 && (last.t - 4.0).abs() < 1e-12);
+        assert!((last.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!(((last.u - first.u).abs() - 0.25).abs() < 1e-12);
+        assert!((at(0.5).u - first.u).abs() > 0.1);
+    }
+
+    #[test]
+    fn growing_and_moving_follow_the_ray_time() {
+        let growing = Moving::new(
+            unit_sphere(),
+            still(Vec3::new(0.0, 0.0, -5.0)),
+            Placement::new(2.0, 0.0, Vec3::new(0.0, 0.0, -6.0)),
+            2.0,
+            4.0,
+        );
+        let t_at = |time: f64| {
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+            growing.hit(&r, 0.001, f64::INFINITY).unwrap().t
+        };
+        assert!((t_at(2.0) - 4.0).abs() < 1e-12);
+        assert!((t_at(3.0) - 4.0).abs() < 1e-12);
+        assert!((t_at(4.0) - 4.0).abs() < 1e-12);
+        // Held at either end outside the time range
+        assert!((t_at(0.0) - 4.0).abs() < 1e-12);
+        let r = Ray::new(Point3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(growing.hit(&r, 0.001, f64::INFINITY).is_none());
+        let r = Ray::new(Point3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 4.0);
+        assert!(growing.hit(&r, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn box_holds_every_hit_over_the_motion() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        // Off the axis, so turning it sweeps it round
+        let sphere = Sphere::new(Point3::new(2.0, 1.0, 0.0), 0.5, mat);
+        let moving = Moving::new(
+            sphere,
+            Placement::new(1.0, -30.0, Vec3::new(0.0, 0.0, -6.0)),
+            Placement::new(1.5, 120.0, Vec3::new(1.0, -1.0, -6.0)),
+            0.0,
+            1.0,
+        );
+        let bbox = moving.bounding_box().unwrap();
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut hits = 0;
+        for _ in 0..20_000 {
+            let direction = Vec3::new(rng.gen_range(-0.8..0.8), rng.gen_range(-0.6..0.6), -1.0);
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), direction, rng.gen());
+            if let Some(rec) = moving.hit(&r, 0.001, f64::INFINITY) {
+                hits += 1;
+                for axis in 0..3 {
+                    assert!(bbox.minimum[axis] <= rec.p[axis] && rec.p[axis] <= bbox.maximum[axis]);
+                }
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn translated_hit_is_moved_back_by_the_offset() {
+        let offset = Vec3::new(3.0, 0.0, -5.0);
+        let moved = Translate::new(unit_sphere(), offset);
+        let r = Ray::new(Point3::new(3.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = moved.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(3.0, 0.0, -4.0)).length() < 1e-12);
+        // The same normal as the sphere's own at the untranslated point
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let own = unit_sphere().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.normal - own.normal).length() < 1e-12);
+
+        // Nothing is left where the sphere was
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(moved.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn rotated_box_holds_the_rotated_object() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let cube = Cube::new(
+            Point3::new(1.0, -0.5, -0.5),
+            Point3::new(3.0, 0.5, 0.5),
+            mat,
+        );
+        let turned = RotateY::new(cube, 30.0);
+        let bbox = turned.bounding_box().unwrap();
+
+        // The far end of the cube is turned towards -z, counter-clockwise seen from above
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        let r = Ray::new(
+            Point3::new(2.9 * cos, 5.0, -2.9 * sin),
+            Vec3::new(0.0, -1.0, 0.0),
+            0.0,
+        );
+        let rec = turned.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut hits = 0;
+        for _ in 0..20_000 {
+            let direction = Vec3::random_unit_vector(&mut rng);
+            let r = Ray::new(Point3::new(0.0, 3.0, 0.0), direction, 0.0);
+            if let Some(rec) = turned.hit(&r, 0.001, f64::INFINITY) {
+                hits += 1;
+                for axis in 0..3 {
+                    assert!(bbox.minimum[axis] <= rec.p[axis] && rec.p[axis] <= bbox.maximum[axis]);
+                }
+                // The normal is turned with the surface and stays unit length
+                assert!((rec.normal.length() - 1.0).abs() < 1e-12);
+            }
+        }
+        assert!(hits > 100);
+        // Reaching out to the turned corners, not the unturned box
+        assert!((bbox.minimum.z() + 3.0 * sin + 0.5 * cos).abs() < 1e-12);
+        assert!((bbox.maximum.x() - (3.0 * cos + 0.5 * sin)).abs() < 1e-12);
+    }
+}
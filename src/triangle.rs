@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use crate::aabb::{Aabb, BOX_PADDING};
+use crate::material::Scatter;
+
+use super::hit::{Hit, HitRecord};
+use super::ray::Ray;
+use super::vec::{Point3, Vec3};
+
+pub struct Triangle {
+    v0: Point3,
+    v1: Point3,
+    v2: Point3,
+    // Shading normals at v0, v1 and v2, interpolated across the face. Flat without them.
+    normals: Option<[Vec3; 3]>,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Triangle {
+    // Counter-clockwise vertices, as seen from the front
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Scatter>) -> Triangle {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            normals: None,
+            mat,
+        }
+    }
+
+    pub fn with_normals(self, n0: Vec3, n1: Vec3, n2: Vec3) -> Triangle {
+        Triangle {
+            normals: Some([n0, n1, n2]),
+            ..self
+        }
+    }
+}
+
+impl Hit for Triangle {
+    // Möller–Trumbore: solve for the distance and barycentric coordinates at once
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        const EPS: f64 = 1.0e-12;
+        let edge1 = self.v1 - self.v0;
+        let edge2 = self.v2 - self.v0;
+
+        let pvec = r.direction().cross(edge2);
+        let det = edge1.dot(pvec);
+        if det.abs() < EPS {
+            // Ray parallel to the triangle's plane
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin() - self.v0;
+        let u = tvec.dot(pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let v = r.direction().dot(qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let mut rec = HitRecord {
+            t,
+            p: r.at(t),
+            mat: self.mat.clone(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+        };
+        // Front and back are the geometric face's, the shading normal only bends the normal
+        rec.set_face_normal(r, edge1.cross(edge2).normalized());
+        if let Some([n0, n1, n2]) = self.normals {
+            let shading = ((1.0 - u - v) * n0 + u * n1 + v * n2).normalized();
+            rec.normal = if rec.front_face { shading } else { -shading };
+        }
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<&dyn Scatter> {
+        Some(self.mat.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        let corner = |pick: fn(f64, f64) -> f64| {
+            Point3::new(
+                pick(pick(self.v0.x(), self.v1.x()), self.v2.x()),
+                pick(pick(self.v0.y(), self.v1.y()), self.v2.y()),
+                pick(pick(self.v0.z(), self.v1.z()), self.v2.z()),
+            )
+        };
+        Some(Aabb::new(
+            corner(f64::min) - padding,
+            corner(f64::max) + padding,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec::Color;
+
+    fn grey() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // In the z = -2 plane, facing +z
+    fn facing_camera() -> Triangle {
+        Triangle::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Point3::new(1.0, -1.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+            grey(),
+        )
+    }
+
+    #[test]
+    fn hit_gives_the_distance_and_face_normal() {
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = facing_camera().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        // From behind the normal still faces the ray
+        let r = Ray::new(Point3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = facing_camera().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn misses_outside_the_edges_and_in_the_plane() {
+        let outside = Ray::new(Point3::new(0.9, 0.9, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(facing_camera()
+            .hit(&outside, 0.001, f64::INFINITY)
+            .is_none());
+        let parallel = Ray::new(Point3::new(-5.0, 0.0, -2.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(facing_camera()
+            .hit(&parallel, 0.001, f64::INFINITY)
+            .is_none());
+        let short = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(facing_camera().hit(&short, 0.001, 1.5).is_none());
+    }
+
+    #[test]
+    fn shading_normal_is_interpolated_between_the_vertex_normals() {
+        let (n0, n1, n2) = (
+            Vec3::new(-1.0, 0.0, 1.0).normalized(),
+            Vec3::new(1.0, 0.0, 1.0).normalized(),
+            Vec3::new(0.0, 1.0, 1.0).normalized(),
+        );
+        let smooth = facing_camera().with_normals(n0, n1, n2);
+        let normal_at = |x: f64, y: f64| {
+            let r = Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            smooth.hit(&r, 0.001, f64::INFINITY).unwrap().normal
+        };
+
+        // Close to a vertex the normal is close to that vertex's
+        assert!((normal_at(-0.98, -0.99) - n0).length() < 0.02);
+        assert!((normal_at(0.98, -0.99) - n1).length() < 0.02);
+        assert!((normal_at(0.0, 0.98) - n2).length() < 0.02);
+        // Halfway along the bottom edge it is the normalized mean of the two
+        let mid = normal_at(0.0, -1.0 + 1e-9);
+        assert!((mid - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        // It varies across the face, and stays unit length
+        let (a, b) = (normal_at(-0.3, -0.5), normal_at(0.3, -0.5));
+        assert!((a - b).length() > 0.1);
+        assert!((a.length() - 1.0).abs() < 1e-12);
+
+        // From behind it is flipped to face the ray, like the flat normal
+        let r = Ray::new(
+            Point3::new(0.0, -1.0 + 1e-9, -4.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let back = smooth.hit(&r, 0.001, f64::INFINITY).unwrap().normal;
+        assert!((back - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-6);
+    }
+}
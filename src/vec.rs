@@ -1,12 +1,21 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use std::fmt::{self, Display};
 use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Range, Sub, SubAssign,
 };
+use std::slice;
+use std::str::FromStr;
+
+// Storage of the three components. The `simd` feature pads it to four lanes, so arithmetic
+// on whole vectors maps onto vector registers. The padding lane is never read.
+#[cfg(not(feature = "simd"))]
+type Lanes = [f64; 3];
+#[cfg(feature = "simd")]
+type Lanes = [f64; 4];
 
 #[derive(Clone, Copy)]
 pub struct Vec3 {
-    e: [f64; 3],
+    e: Lanes,
 }
 
 pub type Point3 = Vec3;
@@ -14,7 +23,33 @@ pub type Color = Vec3;
 
 impl Vec3 {
     pub fn new(e0: f64, e1: f64, e2: f64) -> Vec3 {
-        Vec3 { e: [e0, e1, e2] }
+        #[cfg(not(feature = "simd"))]
+        let e = [e0, e1, e2];
+        #[cfg(feature = "simd")]
+        let e = [e0, e1, e2, 0.0];
+        Vec3 { e }
+    }
+
+    // Lane by lane, padding included, so the loop compiles to a few vector instructions
+    fn zip(self, other: Vec3, f: impl Fn(f64, f64) -> f64) -> Vec3 {
+        let mut e = self.e;
+        for (lane, other) in e.iter_mut().zip(other.e) {
+            *lane = f(*lane, other);
+        }
+        Vec3 { e }
+    }
+
+    fn map(self, f: impl Fn(f64) -> f64) -> Vec3 {
+        Vec3 { e: self.e.map(f) }
+    }
+
+    // Components rotated by one and by two places, the shuffles of the cross product
+    fn yzx(self) -> Vec3 {
+        Vec3::new(self[1], self[2], self[0])
+    }
+
+    fn zxy(self) -> Vec3 {
+        Vec3::new(self[2], self[0], self[1])
     }
 
     pub fn x(self) -> f64 {
@@ -29,8 +64,18 @@ impl Vec3 {
         self[2]
     }
 
+    // Products lane by lane, then summed in x, y, z order like the scalar formula
     pub fn dot(self, other: Vec3) -> f64 {
-        self.x() * other.x() + self.y() * other.y() + self.z() * other.z()
+        let products = self * other;
+        products[0] + products[1] + products[2]
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, f64> {
+        self.e[..3].iter()
+    }
+
+    pub fn max_component(self) -> f64 {
+        self[0].max(self[1]).max(self[2])
     }
 
     pub fn length(self) -> f64 {
@@ -42,28 +87,40 @@ impl Vec3 {
     }
 
     pub fn cross(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            e: [
-                self[1] * other[2] - self[2] * other[1],
-                self[2] * other[0] - self[0] * other[2],
-                self[0] * other[1] - self[1] * other[0],
-            ],
-        }
+        self.yzx() * other.zxy() - self.zxy() * other.yzx()
     }
 
     pub fn normalized(self) -> Vec3 {
         self / self.length()
     }
 
+    // Linear interpolation, self at t = 0 and other at t = 1
+    pub fn lerp(self, other: Vec3, t: f64) -> Vec3 {
+        (1.0 - t) * self + t * other
+    }
+
+    // Rotation about the +y axis by `angle` radians
+    pub fn rotate_y(self, angle: f64) -> Vec3 {
+        let (sin_theta, cos_theta) = angle.sin_cos();
+        Vec3::new(
+            cos_theta * self[0] + sin_theta * self[2],
+            self[1],
+            -sin_theta * self[0] + cos_theta * self[2],
+        )
+    }
+
     pub fn near_zero(self) -> bool {
         const EPS: f64 = 1.0e-8;
-        self[0].abs() < EPS && self[1].abs() < EPS && self[2].abs() < EPS
+        self.iter().all(|e| e.abs() < EPS)
     }
 
     pub fn reflect(self, n: Vec3) -> Vec3 {
         self - 2.0 * self.dot(n) * n
     }
 
+    // Snell's law for a unit direction and a unit normal on the incoming side, i.e. against
+    // the direction. The result continues through the surface, away from n. Past the
+    // critical angle it is meaningless, so callers check for total internal reflection first.
     pub fn refract(self, n: Vec3, etai_over_etat: f64) -> Vec3 {
         let cos_theta = (-self).dot(n).min(1.0);
         let r_out_perp = etai_over_etat * (self + cos_theta * n);
@@ -71,40 +128,29 @@ impl Vec3 {
         r_out_perp + r_out_parallel
     }
 
-    pub fn format_color(self, samples_per_pixel: u64) -> String {
-        let scale = 1.0 / samples_per_pixel as f64;
-        let ir = (256.0 * (self[0] * scale).sqrt().clamp(0.0, 0.999)) as u64;
-        let ig = (256.0 * (self[1] * scale).sqrt().clamp(0.0, 0.999)) as u64;
-        let ib = (256.0 * (self[2] * scale).sqrt().clamp(0.0, 0.999)) as u64;
-        format!("{} {} {}", ir, ig, ib)
-    }
-
-    pub fn random(r: Range<f64>) -> Vec3 {
-        let mut rng = thread_rng();
-        Vec3 {
-            e: [
-                rng.gen_range(r.clone()),
-                rng.gen_range(r.clone()),
-                rng.gen_range(r),
-            ],
-        }
+    pub fn random<R: Rng + ?Sized>(r: Range<f64>, rng: &mut R) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(r.clone()),
+            rng.gen_range(r.clone()),
+            rng.gen_range(r),
+        )
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Self::random_in_unit_sphere().normalized()
+    pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
+        Self::random_in_unit_sphere(rng).normalized()
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
+    pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
-            let v = Vec3::random(-1.0..1.0);
+            let v = Vec3::random(-1.0..1.0, rng);
             if v.length_squared() < 1.0 {
                 return v;
             }
         }
     }
 
-    pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-        let in_unit_sphere = Self::random_in_unit_sphere();
+    pub fn random_in_hemisphere<R: Rng + ?Sized>(normal: Vec3, rng: &mut R) -> Vec3 {
+        let in_unit_sphere = Self::random_in_unit_sphere(rng);
         if in_unit_sphere.dot(normal) > 0.0 {
             in_unit_sphere
         } else {
@@ -112,8 +158,7 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = thread_rng();
+    pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
             let v = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
             if v.length() < 1.0 {
@@ -125,19 +170,59 @@ impl Vec3 {
     pub fn into_color(self) -> Color {
         self
     }
+
+    pub fn luminance(self) -> f64 {
+        // Rec. 709 weights for linear RGB
+        0.2126 * self[0] + 0.7152 * self[1] + 0.0722 * self[2]
+    }
+}
+
+impl From<[f64; 3]> for Vec3 {
+    fn from([e0, e1, e2]: [f64; 3]) -> Vec3 {
+        Vec3::new(e0, e1, e2)
+    }
+}
+
+impl From<(f64, f64, f64)> for Vec3 {
+    fn from((e0, e1, e2): (f64, f64, f64)) -> Vec3 {
+        Vec3::new(e0, e1, e2)
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(v: Vec3) -> [f64; 3] {
+        [v[0], v[1], v[2]]
+    }
+}
+
+// Three comma separated components, as in "0.4,0.3,0.2"
+impl FromStr for Vec3 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Vec3, String> {
+        let e: Vec<f64> = s
+            .split(',')
+            .map(|component| component.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("invalid vector {:?}: {}", s, err))?;
+        match e[..] {
+            [e0, e1, e2] => Ok(Vec3::new(e0, e1, e2)),
+            _ => Err(format!("invalid vector {:?}: expected 3 components", s)),
+        }
+    }
 }
 
 impl Index<usize> for Vec3 {
     type Output = f64;
 
     fn index(&self, index: usize) -> &f64 {
-        &self.e[index]
+        &self.e[..3][index]
     }
 }
 
 impl IndexMut<usize> for Vec3 {
     fn index_mut(&mut self, index: usize) -> &mut f64 {
-        &mut self.e[index]
+        &mut self.e[..3][index]
     }
 }
 
@@ -145,25 +230,13 @@ impl Add for Vec3 {
     type Output = Vec3;
 
     fn add(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            e: [
-                self.x() + other.x(),
-                self.y() + other.y(),
-                self.z() + other.z(),
-            ],
-        }
+        self.zip(other, |a, b| a + b)
     }
 }
 
 impl AddAssign for Vec3 {
-    fn add_assign(&mut self, other: Vec3) -> () {
-        *self = Vec3 {
-            e: [
-                self.x() + other.x(),
-                self.y() + other.y(),
-                self.z() + other.z(),
-            ],
-        };
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
     }
 }
 
@@ -171,25 +244,13 @@ impl Sub for Vec3 {
     type Output = Vec3;
 
     fn sub(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            e: [
-                self.x() - other.x(),
-                self.y() - other.y(),
-                self.z() - other.z(),
-            ],
-        }
+        self.zip(other, |a, b| a - b)
     }
 }
 
 impl SubAssign for Vec3 {
-    fn sub_assign(&mut self, other: Vec3) -> () {
-        *self = Vec3 {
-            e: [
-                self.x() - other.x(),
-                self.y() - other.y(),
-                self.z() - other.z(),
-            ],
-        };
+    fn sub_assign(&mut self, other: Vec3) {
+        *self = *self - other;
     }
 }
 
@@ -197,17 +258,13 @@ impl Mul<f64> for Vec3 {
     type Output = Vec3;
 
     fn mul(self, other: f64) -> Vec3 {
-        Vec3 {
-            e: [self.x() * other, self.y() * other, self.z() * other],
-        }
+        self.map(|a| a * other)
     }
 }
 
 impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, other: f64) -> () {
-        *self = Vec3 {
-            e: [self.x() * other, self.y() * other, self.z() * other],
-        };
+    fn mul_assign(&mut self, other: f64) {
+        *self = *self * other;
     }
 }
 
@@ -215,31 +272,21 @@ impl Mul for Vec3 {
     type Output = Vec3;
 
     fn mul(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            e: [
-                self.x() * other.x(),
-                self.y() * other.y(),
-                self.z() * other.z(),
-            ],
-        }
+        self.zip(other, |a, b| a * b)
     }
 }
 impl Mul<Vec3> for f64 {
     type Output = Vec3;
 
     fn mul(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            e: [self * other.x(), self * other.y(), self * other.z()],
-        }
+        other.map(|b| self * b)
     }
 }
 
 impl Neg for Vec3 {
     type Output = Vec3;
     fn neg(self) -> Self::Output {
-        Vec3 {
-            e: [-self.x(), -self.y(), -self.z()],
-        }
+        self.map(|a| -a)
     }
 }
 
@@ -247,17 +294,13 @@ impl Div<f64> for Vec3 {
     type Output = Vec3;
 
     fn div(self, other: f64) -> Vec3 {
-        Vec3 {
-            e: [self.x() / other, self.y() / other, self.z() / other],
-        }
+        self.map(|a| a / other)
     }
 }
 
 impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, other: f64) -> () {
-        *self = Vec3 {
-            e: [self.x() / other, self.y() / other, self.z() / other],
-        };
+    fn div_assign(&mut self, other: f64) {
+        *self = *self / other;
     }
 }
 
@@ -266,3 +309,122 @@ impl Display for Vec3 {
         write!(f, "({}, {}, {})", self.x(), self.y(), self.z())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1.0e-12, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn dot_sums_the_products() {
+        let (a, b) = (Vec3::new(1.0, 2.0, 3.0), Vec3::new(4.0, -5.0, 6.0));
+        assert_eq!(a.dot(b), 12.0);
+        assert_eq!(a.dot(b), b.dot(a));
+    }
+
+    #[test]
+    fn cross_is_anti_commutative_and_orthogonal() {
+        let (a, b) = (Vec3::new(1.0, 2.0, 3.0), Vec3::new(-2.0, 0.5, 4.0));
+        assert_close(a.cross(b), -b.cross(a));
+        assert_eq!(a.cross(b).dot(a), 0.0);
+        assert_eq!(a.cross(b).dot(b), 0.0);
+        let (x, y) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(x.cross(y), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn reflect_reverses_the_normal_component() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let reflected = Vec3::new(1.0, -2.0, 3.0).reflect(n);
+        assert_close(reflected, Vec3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn refract_follows_snells_law() {
+        // 45 degrees into glass from air: sin(theta') = sin(45) / 1.5
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let incoming = Vec3::new(1.0, -1.0, 0.0).normalized();
+        let refracted = incoming.refract(n, 1.0 / 1.5);
+        assert!((refracted.length() - 1.0).abs() < 1.0e-12);
+        assert!((refracted.x() - 0.5f64.sqrt() / 1.5).abs() < 1.0e-12);
+        // Goes on through the surface, away from the normal
+        assert!(refracted.y() < 0.0);
+        assert_eq!(refracted.z(), 0.0);
+
+        // Straight on it passes through unbent whatever the ratio
+        let straight = Vec3::new(0.0, -1.0, 0.0);
+        assert_close(straight.refract(n, 1.0 / 1.5), straight);
+    }
+
+    #[test]
+    fn near_zero_below_the_epsilon_only() {
+        assert!(Vec3::new(0.0, 0.0, 0.0).near_zero());
+        assert!(Vec3::new(0.9e-8, -0.9e-8, 0.0).near_zero());
+        assert!(!Vec3::new(1.0e-8, 0.0, 0.0).near_zero());
+        assert!(!Vec3::new(0.0, 0.0, -1.1e-8).near_zero());
+    }
+
+    #[test]
+    fn normalized_has_unit_length() {
+        for v in [Vec3::new(3.0, 4.0, 0.0), Vec3::new(-1.0e-3, 2.0e-3, 5.0e-4)].iter() {
+            let unit = v.normalized();
+            assert!((unit.length() - 1.0).abs() < 1.0e-12);
+            assert_close(unit * v.length(), *v);
+        }
+    }
+
+    #[test]
+    fn converts_from_and_into_arrays_and_tuples() {
+        let v = Vec3::new(1.5, -2.0, 3.25);
+        assert_close(Vec3::from([1.5, -2.0, 3.25]), v);
+        assert_close((1.5, -2.0, 3.25).into(), v);
+        let array: [f64; 3] = v.into();
+        assert_eq!(array, [1.5, -2.0, 3.25]);
+        assert_close(Vec3::from(array), v);
+    }
+
+    #[test]
+    fn iter_walks_the_three_components() {
+        let v = Vec3::new(1.5, -2.0, 3.25);
+        assert_eq!(v.iter().copied().collect::<Vec<_>>(), vec![1.5, -2.0, 3.25]);
+        assert_eq!(v.iter().sum::<f64>(), 2.75);
+        assert_eq!(v.iter().map(|e| e * e).sum::<f64>(), v.length_squared());
+    }
+
+    // The padded layout against the textbook formulas on plain arrays
+    #[cfg(feature = "simd")]
+    #[test]
+    fn padded_lanes_match_the_scalar_formulas() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let close = |a: f64, b: f64| (a - b).abs() <= 1.0e-12 * (1.0 + a.abs().max(b.abs()));
+        let close3 = |v: Vec3, e: [f64; 3]| v.iter().zip(e).all(|(&a, b)| close(a, b));
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let (a, b) = (
+                Vec3::random(-1.0e3..1.0e3, &mut rng),
+                Vec3::random(-1.0..1.0, &mut rng),
+            );
+            let ([ax, ay, az], [bx, by, bz]): ([f64; 3], [f64; 3]) = (a.into(), b.into());
+            let s = rng.gen_range(-10.0..10.0);
+
+            assert!(close(a.dot(b), ax * bx + ay * by + az * bz));
+            assert!(close(a.length_squared(), ax * ax + ay * ay + az * az));
+            assert!(close3(
+                a.cross(b),
+                [ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx],
+            ));
+            assert!(close3(a + b, [ax + bx, ay + by, az + bz]));
+            assert!(close3(a - b, [ax - bx, ay - by, az - bz]));
+            assert!(close3(a * b, [ax * bx, ay * by, az * bz]));
+            assert!(close3(a * s, [ax * s, ay * s, az * s]));
+            assert!(close3(a / s, [ax / s, ay / s, az / s]));
+            assert!(close3(-a, [-ax, -ay, -az]));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// A material written outside the crate, through the public trait alone
+
+use std::sync::Arc;
+
+use rand::RngCore;
+use rayrust::camera::CameraBuilder;
+use rayrust::hit::{Background, HitRecord, WorldBuilder};
+use rayrust::ray::Ray;
+use rayrust::vec::{Color, Point3};
+use rayrust::{render, Material, Settings};
+
+// Takes every ray that reaches it
+struct Absorber;
+
+impl Material for Absorber {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+}
+
+#[test]
+fn absorber_renders_black_against_a_white_background() {
+    let world = WorldBuilder::new()
+        .background(Background::SolidColor(Color::new(1.0, 1.0, 1.0)))
+        .sphere(Point3::new(0.0, 0.0, -2.0), 0.5, Arc::new(Absorber))
+        .build();
+    // Narrow enough that every camera ray lands on the sphere
+    let cam = CameraBuilder::new().vfov(5.0).build();
+    let settings = Settings {
+        seed: Some(1),
+        ..Settings::new(2, 2, 1)
+    };
+
+    let image = render(&world, &cam, &settings);
+    for pixel in &image.pixels {
+        assert_eq!(pixel.color.length(), 0.0);
+    }
+
+    // And the same rays are white with the sphere gone
+    let empty = WorldBuilder::new()
+        .background(Background::SolidColor(Color::new(1.0, 1.0, 1.0)))
+        .build();
+    let image = render(&empty, &cam, &settings);
+    assert!(image.pixels.iter().all(|pixel| pixel.color.length() > 1.0));
+}
@@ -0,0 +1,121 @@
+// Small renders of built-in scenes compared against the PNGs in tests/golden. Renders are
+// seeded, so they only drift when the renderer changes. Run with BLESS=1 to write the
+// current images as the new references after checking the change is intended.
+
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use rayrust::camera::CameraBuilder;
+use rayrust::hit::{World, WorldBuilder};
+use rayrust::output::{read_png, write_png};
+use rayrust::postprocess::ToneMap;
+use rayrust::render::stream_rng;
+use rayrust::scene::CameraSettings;
+use rayrust::scenes;
+use rayrust::{render, Settings};
+
+const WIDTH: u64 = 48;
+const HEIGHT: u64 = 27;
+const SAMPLES: u64 = 8;
+const SEED: u64 = 1;
+// In 8-bit levels, room for floating point differences between platforms
+const MAX_RMSE: f64 = 1.0;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+fn rmse(a: &[[u8; 3]], b: &[[u8; 3]]) -> f64 {
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .flat_map(|(a, b)| a.iter().zip(b))
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum();
+    (sum / (3 * a.len()) as f64).sqrt()
+}
+
+// Renders the scene the way the binary does with its default camera
+fn check(name: &str, world: World) {
+    let view = CameraSettings::default();
+    let cam = CameraBuilder::new()
+        .lookfrom(view.lookfrom)
+        .lookat(view.lookat)
+        .vup(view.vup)
+        .vfov(view.vfov)
+        .aperture(view.aperture)
+        .focus_dist(view.focus_dist)
+        .aspect_ratio(WIDTH as f64 / HEIGHT as f64)
+        .build();
+    let settings = Settings {
+        seed: Some(SEED),
+        ..Settings::new(WIDTH, HEIGHT, SAMPLES)
+    };
+    let image = render(&world, &cam, &settings);
+    let colors: Vec<_> = image.pixels.iter().map(|pixel| pixel.color).collect();
+
+    let path = fixture(name);
+    if env::var_os("BLESS").is_some() {
+        let out = BufWriter::new(File::create(&path).unwrap());
+        write_png(out, WIDTH, HEIGHT, SAMPLES, ToneMap::Clamp, 2.0, &colors).unwrap();
+        return;
+    }
+
+    let mut encoded = Vec::new();
+    write_png(
+        &mut encoded,
+        WIDTH,
+        HEIGHT,
+        SAMPLES,
+        ToneMap::Clamp,
+        2.0,
+        &colors,
+    )
+    .unwrap();
+    let (_, _, rendered) = read_png(encoded.as_slice()).unwrap();
+    let file = File::open(&path)
+        .unwrap_or_else(|err| panic!("cannot open {:?}, run with BLESS=1: {}", path, err));
+    let (width, height, golden) = read_png(file).unwrap();
+    assert_eq!((width, height), (WIDTH, HEIGHT), "size of {:?}", path);
+    let error = rmse(&rendered, &golden);
+    assert!(
+        error <= MAX_RMSE,
+        "{} differs from {:?} by RMSE {:.3}, run with BLESS=1 if that is intended",
+        name,
+        path,
+        error
+    );
+}
+
+#[test]
+fn random_scene() {
+    check(
+        "random",
+        scenes::random_scene(WorldBuilder::new(), 11, false, Some(SEED)),
+    );
+}
+
+#[test]
+fn cornell_scene() {
+    check("cornell", scenes::cornell_scene(WorldBuilder::new()));
+}
+
+#[test]
+fn marble_scene() {
+    let world = scenes::marble_scene(WorldBuilder::new(), &mut stream_rng(Some(SEED), 0));
+    check("marble", world);
+}
+
+#[test]
+fn contact_scene() {
+    check("contact", scenes::contact_scene(WorldBuilder::new()));
+}
+
+#[test]
+fn penumbra_scene() {
+    check("penumbra", scenes::penumbra_scene(WorldBuilder::new()));
+}